@@ -0,0 +1,51 @@
+use advent_of_code_2021::day11::{input_generator, play, record, OctopusSim};
+use std::{env, fs, io, num::NonZeroU32, process};
+
+fn usage() -> ! {
+    eprintln!("usage: day11_playback [INPUT] [--fps N] [--steps N] [--record FILE]");
+    process::exit(2);
+}
+
+fn main() -> io::Result<()> {
+    let mut input = String::from("input/2021/day11.txt");
+    let mut fps = NonZeroU32::new(10).unwrap();
+    let mut max_steps = 1_000;
+    let mut record_to = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fps" => {
+                fps = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or_else(|| usage())
+            }
+            "--steps" => {
+                max_steps = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or_else(|| usage())
+            }
+            "--record" => record_to = Some(args.next().unwrap_or_else(|| usage())),
+            "-h" | "--help" => usage(),
+            _ => input = arg,
+        }
+    }
+
+    let grid = input_generator(&fs::read_to_string(input)?);
+    let octopusses = grid.len();
+
+    let mut synced = false;
+    let steps = OctopusSim::new(grid).take(max_steps).take_while(|step| {
+        !synced && {
+            synced = step.flashed.len() == octopusses;
+            true
+        }
+    });
+
+    match record_to {
+        Some(path) => record(steps, &mut io::BufWriter::new(fs::File::create(path)?)),
+        None => play(steps, fps, &mut io::stdout().lock()),
+    }
+}
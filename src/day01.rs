@@ -1,29 +1,133 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::{self, BufRead},
+};
 
 #[aoc_generator(day1)]
 pub fn input_generator(input: &str) -> Vec<u32> {
     input.lines().map(|l| l.parse::<u32>().unwrap()).collect()
 }
 
-#[aoc(day1, part1)]
-pub fn part1(input: &[u32]) -> u32 {
-    input
+pub fn count_increases(readings: &[u32], window: usize) -> u32 {
+    assert!(window > 0, "window must not be empty");
+
+    readings
         .iter()
-        .tuple_windows()
-        .map(|(a, b)| u32::from(b > a))
+        .zip(readings.iter().skip(window))
+        .map(|(leaving, entering)| u32::from(entering > leaving))
         .sum()
 }
 
+pub fn count_increases_streaming(readings: impl BufRead, window: usize) -> io::Result<u32> {
+    assert!(window > 0, "window must not be empty");
+
+    let mut in_window = VecDeque::with_capacity(window + 1);
+    let mut increases = 0;
+
+    for line in readings.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let entering = line
+            .parse::<u32>()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        in_window.push_back(entering);
+
+        if in_window.len() > window {
+            let leaving = in_window.pop_front().unwrap();
+            increases += u32::from(entering > leaving);
+        }
+    }
+
+    Ok(increases)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Run {
+    pub start: usize,
+    pub len: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Descent {
+    pub at: usize,
+    pub amount: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SweepReport {
+    pub increases: u32,
+    pub longest_rise: Run,
+    pub largest_drop: Option<Descent>,
+    pub plateaus: Vec<Run>,
+    pub deltas: BTreeMap<i64, usize>,
+}
+
+impl SweepReport {
+    pub fn new(readings: &[u32]) -> Self {
+        let mut longest_rise = Run {
+            start: 0,
+            len: readings.len().min(1),
+        };
+        let mut rise = longest_rise;
+        let mut largest_drop: Option<Descent> = None;
+        let mut plateaus = Vec::new();
+        let mut plateau: Option<Run> = None;
+        let mut deltas = BTreeMap::new();
+
+        for (i, (&a, &b)) in readings.iter().tuple_windows().enumerate() {
+            *deltas.entry(i64::from(b) - i64::from(a)).or_default() += 1;
+
+            if b > a {
+                rise.len += 1;
+            } else {
+                rise = Run {
+                    start: i + 1,
+                    len: 1,
+                };
+            }
+            if rise.len > longest_rise.len {
+                longest_rise = rise;
+            }
+
+            if b < a && largest_drop.is_none_or(|drop| a - b > drop.amount) {
+                largest_drop = Some(Descent {
+                    at: i + 1,
+                    amount: a - b,
+                });
+            }
+
+            if a == b {
+                plateau.get_or_insert(Run { start: i, len: 1 }).len += 1;
+            } else if let Some(run) = plateau.take() {
+                plateaus.push(run);
+            }
+        }
+        plateaus.extend(plateau);
+
+        Self {
+            increases: count_increases(readings, 1),
+            longest_rise,
+            largest_drop,
+            plateaus,
+            deltas,
+        }
+    }
+}
+
+#[aoc(day1, part1)]
+pub fn part1(input: &[u32]) -> u32 {
+    count_increases(input, 1)
+}
+
 #[aoc(day1, part2)]
 pub fn part2(input: &[u32]) -> u32 {
-    input
-        .iter()
-        .tuple_windows()
-        .map(|(a, b, c)| a + b + c)
-        .tuple_windows()
-        .map(|(a, b)| u32::from(b > a))
-        .sum()
+    count_increases(input, 3)
 }
 
 #[cfg(test)]
@@ -66,4 +170,86 @@ mod tests {
             1797
         );
     }
+
+    #[test]
+    fn windows() {
+        let input = input_generator(EXAMPLE);
+
+        assert_eq!(count_increases(&input, 1), 7);
+        assert_eq!(count_increases(&input, 3), 5);
+        assert_eq!(count_increases(&input, 5), 5);
+        assert_eq!(count_increases(&input, 10), 0);
+        assert_eq!(count_increases(&input, 11), 0);
+    }
+
+    #[test]
+    fn streaming() {
+        let input = include_str!("../input/2021/day1.txt");
+
+        for window in 1..=5 {
+            assert_eq!(
+                count_increases_streaming(io::Cursor::new(input), window).unwrap(),
+                count_increases(&input_generator(input), window)
+            );
+        }
+
+        assert_eq!(
+            count_increases_streaming(io::Cursor::new("1\ntwo\n3"), 1)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn sweep_report() {
+        assert_eq!(
+            SweepReport::new(&input_generator(EXAMPLE)),
+            SweepReport {
+                increases: 7,
+                longest_rise: Run { start: 0, len: 4 },
+                largest_drop: Some(Descent { at: 4, amount: 10 }),
+                plateaus: vec![],
+                deltas: BTreeMap::from([
+                    (-10, 1),
+                    (-9, 1),
+                    (1, 1),
+                    (2, 1),
+                    (3, 1),
+                    (7, 1),
+                    (8, 1),
+                    (29, 1),
+                    (33, 1),
+                ]),
+            }
+        );
+    }
+
+    #[test]
+    fn sweep_report_plateaus() {
+        let report = SweepReport::new(&[5, 5, 1, 2, 2, 2, 3, 4, 5, 3, 3]);
+
+        assert_eq!(report.longest_rise, Run { start: 5, len: 4 });
+        assert_eq!(report.largest_drop, Some(Descent { at: 2, amount: 4 }));
+        assert_eq!(
+            report.plateaus,
+            [
+                Run { start: 0, len: 2 },
+                Run { start: 3, len: 3 },
+                Run { start: 9, len: 2 },
+            ]
+        );
+        assert_eq!(report.deltas[&0], 4);
+
+        assert_eq!(
+            SweepReport::new(&[]),
+            SweepReport {
+                increases: 0,
+                longest_rise: Run { start: 0, len: 0 },
+                largest_drop: None,
+                plateaus: vec![],
+                deltas: BTreeMap::new(),
+            }
+        );
+    }
 }
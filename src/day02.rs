@@ -1,61 +1,243 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
-use std::str::FromStr;
+use std::{error::Error, fmt};
 
-pub enum Movement {
-    Forward,
-    Down,
-    Up,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Forward(i64),
+    Back(i64),
+    Down(i64),
+    Up(i64),
+    ResetAim,
 }
 
-impl FromStr for Movement {
-    type Err = ();
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnknownCommand(String),
+    MissingAmount,
+    InvalidAmount(String),
+    UnexpectedArgument(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub kind: ParseErrorKind,
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "forward" => Ok(Self::Forward),
-            "down" => Ok(Self::Down),
-            "up" => Ok(Self::Up),
-            _ => Err(()),
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            ParseErrorKind::UnknownCommand(command) => write!(f, "unknown command {:?}", command),
+            ParseErrorKind::MissingAmount => write!(f, "missing amount"),
+            ParseErrorKind::InvalidAmount(amount) => write!(f, "invalid amount {:?}", amount),
+            ParseErrorKind::UnexpectedArgument(arg) => write!(f, "unexpected argument {:?}", arg),
         }
     }
 }
 
+impl Error for ParseError {}
+
+fn parse_command(line: &str) -> Result<Option<Command>, ParseErrorKind> {
+    let line = line.split('#').next().unwrap();
+    let mut tokens = line.split_whitespace();
+
+    let command = match tokens.next() {
+        Some(command) => command,
+        None => return Ok(None),
+    };
+
+    let mut amount = || {
+        let amount = tokens.next().ok_or(ParseErrorKind::MissingAmount)?;
+        amount
+            .parse::<u32>()
+            .map(i64::from)
+            .map_err(|_| ParseErrorKind::InvalidAmount(amount.into()))
+    };
+
+    let command = match command {
+        "forward" => Command::Forward(amount()?),
+        "back" => Command::Back(amount()?),
+        "down" => Command::Down(amount()?),
+        "up" => Command::Up(amount()?),
+        "reset-aim" => Command::ResetAim,
+        _ => return Err(ParseErrorKind::UnknownCommand(command.into())),
+    };
+
+    match tokens.next() {
+        Some(arg) => Err(ParseErrorKind::UnexpectedArgument(arg.into())),
+        None => Ok(Some(command)),
+    }
+}
+
 #[aoc_generator(day2)]
-pub fn input_generator(input: &str) -> Vec<(Movement, u32)> {
+pub fn input_generator(input: &str) -> Result<Vec<Command>, ParseError> {
     input
         .lines()
-        .map(|l| {
-            let (movement, amount) = l.splitn(2, ' ').collect_tuple().unwrap();
-            (movement.parse().unwrap(), amount.parse().unwrap())
+        .enumerate()
+        .filter_map(|(i, line)| {
+            parse_command(line)
+                .map_err(|kind| ParseError { line: i + 1, kind })
+                .transpose()
         })
         .collect()
 }
 
-#[aoc(day2, part1)]
-pub fn part1(input: &[(Movement, u32)]) -> u32 {
-    let pos = input
-        .iter()
-        .fold((0, 0), |(hor, depth), (mov, amount)| match mov {
-            Movement::Forward => (hor + amount, depth),
-            Movement::Down => (hor, depth + amount),
-            Movement::Up => (hor, depth - amount),
-        });
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Submarine {
+    pub horizontal: i64,
+    pub depth: i64,
+    pub aim: i64,
+}
 
-    pos.0 * pos.1
+pub trait Steering {
+    fn steer(&self, submarine: &mut Submarine, command: Command);
 }
 
-#[aoc(day2, part2)]
-pub fn part2(input: &[(Movement, u32)]) -> u32 {
-    let pos = input
-        .iter()
-        .fold((0, 0, 0), |(hor, depth, aim), (mov, amount)| match mov {
-            Movement::Down => (hor, depth, aim + amount),
-            Movement::Up => (hor, depth, aim - amount),
-            Movement::Forward => (hor + amount, depth + (aim * amount), aim),
-        });
+pub struct Plain;
+
+impl Steering for Plain {
+    fn steer(&self, submarine: &mut Submarine, command: Command) {
+        match command {
+            Command::Forward(amount) => submarine.horizontal += amount,
+            Command::Back(amount) => submarine.horizontal -= amount,
+            Command::Down(amount) => submarine.depth += amount,
+            Command::Up(amount) => submarine.depth -= amount,
+            Command::ResetAim => submarine.aim = 0,
+        }
+    }
+}
+
+pub struct Aim;
+
+impl Steering for Aim {
+    fn steer(&self, submarine: &mut Submarine, command: Command) {
+        match command {
+            Command::Forward(amount) => {
+                submarine.horizontal += amount;
+                submarine.depth += submarine.aim * amount;
+            }
+            Command::Back(amount) => {
+                submarine.horizontal -= amount;
+                submarine.depth -= submarine.aim * amount;
+            }
+            Command::Down(amount) => submarine.aim += amount,
+            Command::Up(amount) => submarine.aim -= amount,
+            Command::ResetAim => submarine.aim = 0,
+        }
+    }
+}
+
+impl Submarine {
+    pub fn execute(&mut self, steering: &impl Steering, command: Command) {
+        steering.steer(self, command);
+    }
+
+    pub fn run(steering: &impl Steering, commands: &[Command]) -> Self {
+        let mut submarine = Self::default();
+        for &command in commands {
+            submarine.execute(steering, command);
+        }
+        submarine
+    }
+
+    pub fn trajectory(steering: &impl Steering, commands: &[Command]) -> Trajectory {
+        let mut submarine = Self::default();
+        let mut positions = vec![submarine];
+        for &command in commands {
+            submarine.execute(steering, command);
+            positions.push(submarine);
+        }
+        Trajectory(positions)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trajectory(pub Vec<Submarine>);
+
+impl Trajectory {
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("step,horizontal,depth,aim\n");
+        for (step, submarine) in self.0.iter().enumerate() {
+            csv += &format!(
+                "{},{},{},{}\n",
+                step, submarine.horizontal, submarine.depth, submarine.aim
+            );
+        }
+        csv
+    }
+}
+
+const COLOURS: &[&str] = &["steelblue", "darkorange", "seagreen", "crimson"];
+
+fn escape_xml(s: &str) -> String {
+    s.chars().fold(String::new(), |mut out, c| {
+        match c {
+            '&' => out += "&amp;",
+            '<' => out += "&lt;",
+            '>' => out += "&gt;",
+            '"' => out += "&quot;",
+            _ => out.push(c),
+        }
+        out
+    })
+}
+
+pub fn to_svg(trajectories: &[(&str, &Trajectory)]) -> String {
+    let positions = || {
+        trajectories
+            .iter()
+            .flat_map(|(_, trajectory)| &trajectory.0)
+    };
+    let (min_x, max_x) = positions()
+        .map(|submarine| submarine.horizontal)
+        .minmax()
+        .into_option()
+        .unwrap_or((0, 0));
+    let (min_y, max_y) = positions()
+        .map(|submarine| submarine.depth)
+        .minmax()
+        .into_option()
+        .unwrap_or((0, 0));
+    let pad = ((max_x - min_x).max(max_y - min_y) / 20).max(1);
 
-    pos.0 * pos.1
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">\n",
+        min_x - pad,
+        min_y - pad,
+        max_x - min_x + 2 * pad,
+        max_y - min_y + 2 * pad
+    );
+
+    for (i, (name, trajectory)) in trajectories.iter().enumerate() {
+        let points = trajectory
+            .0
+            .iter()
+            .map(|submarine| format!("{},{}", submarine.horizontal, submarine.depth))
+            .join(" ");
+        svg += &format!(
+            "  <polyline fill=\"none\" stroke=\"{}\" stroke-width=\"2\" vector-effect=\"non-scaling-stroke\" points=\"{}\"><title>{}</title></polyline>\n",
+            COLOURS[i % COLOURS.len()],
+            points,
+            escape_xml(name)
+        );
+    }
+
+    svg += "</svg>\n";
+    svg
+}
+
+#[aoc(day2, part1)]
+pub fn part1(input: &[Command]) -> i64 {
+    let submarine = Submarine::run(&Plain, input);
+    submarine.horizontal * submarine.depth
+}
+
+#[aoc(day2, part2)]
+pub fn part2(input: &[Command]) -> i64 {
+    let submarine = Submarine::run(&Aim, input);
+    submarine.horizontal * submarine.depth
 }
 
 #[cfg(test)]
@@ -71,27 +253,126 @@ forward 2";
 
     #[test]
     fn example1() {
-        assert_eq!(part1(&input_generator(EXAMPLE)), 150);
+        assert_eq!(part1(&input_generator(EXAMPLE).unwrap()), 150);
     }
 
     #[test]
     fn solution1() {
         assert_eq!(
-            part1(&input_generator(include_str!("../input/2021/day2.txt"))),
+            part1(&input_generator(include_str!("../input/2021/day2.txt")).unwrap()),
             2_039_912
         );
     }
 
     #[test]
     fn example2() {
-        assert_eq!(part2(&input_generator(EXAMPLE)), 900);
+        assert_eq!(part2(&input_generator(EXAMPLE).unwrap()), 900);
     }
 
     #[test]
     fn solution2() {
         assert_eq!(
-            part2(&input_generator(include_str!("../input/2021/day2.txt"))),
+            part2(&input_generator(include_str!("../input/2021/day2.txt")).unwrap()),
             1_942_068_080,
         );
     }
+
+    #[test]
+    fn extended_commands() {
+        let commands = input_generator(
+            "# dive, then come back up past the surface
+down 5
+forward 10 # cruise
+up 7
+back 4
+
+reset-aim
+forward 1",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Submarine::run(&Plain, &commands),
+            Submarine {
+                horizontal: 7,
+                depth: -2,
+                aim: 0,
+            }
+        );
+        assert_eq!(
+            Submarine::run(&Aim, &commands),
+            Submarine {
+                horizontal: 7,
+                depth: 58,
+                aim: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |input, line, kind| {
+            assert_eq!(input_generator(input), Err(ParseError { line, kind }));
+        };
+
+        error(
+            "forward 1\nsideways 2",
+            2,
+            ParseErrorKind::UnknownCommand("sideways".into()),
+        );
+        error("\n# comment\nup", 3, ParseErrorKind::MissingAmount);
+        error("down -3", 1, ParseErrorKind::InvalidAmount("-3".into()));
+        error(
+            "forward 1\nreset-aim 4",
+            2,
+            ParseErrorKind::UnexpectedArgument("4".into()),
+        );
+        error(
+            "forward 1 2",
+            1,
+            ParseErrorKind::UnexpectedArgument("2".into()),
+        );
+    }
+
+    #[test]
+    fn trajectory_csv() {
+        let commands = input_generator(EXAMPLE).unwrap();
+
+        assert_eq!(
+            Submarine::trajectory(&Aim, &commands).to_csv(),
+            "step,horizontal,depth,aim
+0,0,0,0
+1,5,0,0
+2,5,0,5
+3,13,40,5
+4,13,40,2
+5,13,40,10
+6,15,60,10
+"
+        );
+
+        let trajectory = Submarine::trajectory(&Plain, &commands);
+        assert_eq!(
+            trajectory.0.last(),
+            Some(&Submarine::run(&Plain, &commands))
+        );
+    }
+
+    #[test]
+    fn trajectory_svg() {
+        let commands = input_generator(EXAMPLE).unwrap();
+        let plain = Submarine::trajectory(&Plain, &commands);
+        let aim = Submarine::trajectory(&Aim, &commands);
+
+        let svg = to_svg(&[("plain", &plain), ("aim", &aim)]);
+        assert!(
+            svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"-3 -3 21 66\">")
+        );
+        assert!(svg.contains("points=\"0,0 5,0 5,5 13,5 13,2 13,10 15,10\"><title>plain</title>"));
+        assert!(svg.contains("points=\"0,0 5,0 5,0 13,40 13,40 13,40 15,60\"><title>aim</title>"));
+        assert!(svg.ends_with("</svg>\n"));
+
+        let svg = to_svg(&[("<plain & \"aim\">", &plain)]);
+        assert!(svg.contains("<title>&lt;plain &amp; &quot;aim&quot;&gt;</title>"));
+    }
 }
@@ -1,86 +1,130 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 
-#[aoc_generator(day3)]
-pub fn input_generator(input: &str) -> Vec<String> {
-    input.lines().map(|line| line.to_owned()).collect()
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub width: u32,
+    pub readings: Vec<u128>,
 }
 
-fn bin_iter_to_decimal(it: impl DoubleEndedIterator<Item = u32>) -> u32 {
-    it.rev().zip(0..).map(|(n, pow)| n * 2_u32.pow(pow)).sum()
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BitCount {
+    pub zeros: usize,
+    pub ones: usize,
 }
 
-#[aoc(day3, part1)]
-pub fn part1(input: &[String]) -> u32 {
-    let width = input[0].len();
-    let mut occurrences = vec![(0, 0); width];
-
-    for n in input {
-        for (c, mut occ) in n.chars().zip(occurrences.iter_mut()) {
-            if let '0' = c {
-                occ.0 += 1;
-            } else {
-                occ.1 += 1;
-            }
-        }
+#[aoc_generator(day3)]
+pub fn input_generator(input: &str) -> Report {
+    let width = input.lines().next().map_or(0, str::len);
+    assert!(width <= u128::BITS as usize, "readings wider than 128 bits");
+
+    let readings = input
+        .lines()
+        .map(|line| {
+            assert_eq!(line.len(), width, "reading {:?} has the wrong width", line);
+            assert!(line.bytes().all(|b| b == b'0' || b == b'1'));
+            u128::from_str_radix(line, 2).unwrap()
+        })
+        .collect();
+
+    Report {
+        width: width as u32,
+        readings,
     }
+}
 
-    let gamma_rate = bin_iter_to_decimal(
-        occurrences
-            .iter()
-            .map(|(zeroes, ones)| if zeroes > ones { 0 } else { 1 }),
-    );
-
-    let epsilon_rate = gamma_rate ^ ((1 << width) - 1);
+impl Report {
+    fn mask(&self) -> u128 {
+        u128::MAX.checked_shr(u128::BITS - self.width).unwrap_or(0)
+    }
 
-    gamma_rate * epsilon_rate
-}
+    fn is_set(&self, reading: u128, column: u32) -> bool {
+        reading >> (self.width - 1 - column) & 1 == 1
+    }
 
-#[aoc(day3, part2)]
-pub fn part2(input: &[String]) -> u32 {
-    fn find_oxygen_generator_rating(mut input: Vec<String>) -> u32 {
-        for bit in 0.. {
-            if input.len() == 1 {
-                return u32::from_str_radix(&input[0], 2).unwrap();
-            }
+    pub fn column_counts(&self) -> Vec<BitCount> {
+        (0..self.width)
+            .map(|column| {
+                let ones = self
+                    .readings
+                    .iter()
+                    .filter(|&&reading| self.is_set(reading, column))
+                    .count();
+
+                BitCount {
+                    zeros: self.readings.len() - ones,
+                    ones,
+                }
+            })
+            .collect()
+    }
 
-            let ones = itertools::partition(&mut input, |s| s.chars().nth(bit).unwrap() == '1');
-            let zeroes = input.len() - ones;
-            let split_index = ones;
+    pub fn gamma(&self) -> u128 {
+        self.column_counts().iter().fold(0, |gamma, count| {
+            gamma << 1 | u128::from(count.ones >= count.zeros)
+        })
+    }
 
-            if ones >= zeroes {
-                let _ = input.split_off(split_index);
-            } else {
-                input = input.split_off(split_index);
-            }
-        }
+    pub fn epsilon(&self) -> u128 {
+        !self.gamma() & self.mask()
+    }
 
-        unreachable!()
+    fn sorted(&self) -> Vec<u128> {
+        let mut sorted = self.readings.clone();
+        sorted.sort_unstable();
+        sorted
     }
 
-    fn find_co2_scrubber_rating(mut input: Vec<String>) -> u32 {
-        for bit in 0.. {
-            if input.len() == 1 {
-                return u32::from_str_radix(&input[0], 2).unwrap();
+    // Readings sharing a prefix are contiguous once sorted, and within them
+    // those with a 0 in the next column come first, so every criterion only
+    // narrows a range.
+    fn narrow(&self, sorted: &[u128], criterion: impl Fn(BitCount) -> bool) -> Option<u128> {
+        let mut candidates = sorted;
+
+        for column in 0..self.width {
+            if candidates.len() <= 1 {
+                break;
             }
 
-            let zeroes = itertools::partition(&mut input, |s| s.chars().nth(bit).unwrap() == '0');
-            let ones = input.len() - zeroes;
-            let split_index = zeroes;
+            let split = candidates.partition_point(|&reading| !self.is_set(reading, column));
+            let count = BitCount {
+                zeros: split,
+                ones: candidates.len() - split,
+            };
 
-            if ones >= zeroes {
-                let _ = input.split_off(split_index);
+            candidates = if criterion(count) {
+                &candidates[split..]
             } else {
-                input = input.split_off(split_index);
-            }
+                &candidates[..split]
+            };
         }
 
-        unreachable!()
+        candidates.first().copied()
+    }
+
+    pub fn filter_by_bit_criteria(&self, criterion: impl Fn(BitCount) -> bool) -> Option<u128> {
+        self.narrow(&self.sorted(), criterion)
+    }
+
+    pub fn life_support_ratings(&self) -> (Option<u128>, Option<u128>) {
+        let sorted = self.sorted();
+
+        (
+            self.narrow(&sorted, |count| count.ones >= count.zeros),
+            self.narrow(&sorted, |count| count.ones < count.zeros),
+        )
     }
+}
+
+#[aoc(day3, part1)]
+pub fn part1(report: &Report) -> Option<u128> {
+    report.gamma().checked_mul(report.epsilon())
+}
 
-    let oxygen_generator_rating = find_oxygen_generator_rating(input.to_vec());
-    let co2_scrubber_rating = find_co2_scrubber_rating(input.to_vec());
+#[aoc(day3, part2)]
+pub fn part2(report: &Report) -> Option<u128> {
+    let (oxygen_generator_rating, co2_scrubber_rating) = report.life_support_ratings();
 
-    oxygen_generator_rating * co2_scrubber_rating
+    oxygen_generator_rating?.checked_mul(co2_scrubber_rating?)
 }
 
 #[cfg(test)]
@@ -102,27 +146,78 @@ mod tests {
 
     #[test]
     fn example1() {
-        assert_eq!(part1(&input_generator(EXAMPLE)), 198);
+        assert_eq!(part1(&input_generator(EXAMPLE)), Some(198));
     }
 
     #[test]
     fn solution1() {
         assert_eq!(
             part1(&input_generator(include_str!("../input/2021/day3.txt"))),
-            1_025_636
+            Some(1_025_636)
         );
     }
 
     #[test]
     fn example2() {
-        assert_eq!(part2(&input_generator(EXAMPLE)), 230);
+        assert_eq!(part2(&input_generator(EXAMPLE)), Some(230));
     }
 
     #[test]
     fn solution2() {
         assert_eq!(
             part2(&input_generator(include_str!("../input/2021/day3.txt"))),
-            793_873,
+            Some(793_873),
+        );
+    }
+
+    #[test]
+    fn column_counts() {
+        let report = input_generator(EXAMPLE);
+
+        assert_eq!(report.width, 5);
+        assert_eq!(
+            report.column_counts(),
+            [(5, 7), (7, 5), (4, 8), (5, 7), (7, 5)]
+                .iter()
+                .map(|&(zeros, ones)| BitCount { zeros, ones })
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(report.gamma(), 22);
+        assert_eq!(report.epsilon(), 9);
+    }
+
+    #[test]
+    fn wide_readings() {
+        let report = input_generator(&format!(
+            "{0}{1}\n{0}{1}\n{1}{0}",
+            "1".repeat(60),
+            "0".repeat(60)
+        ));
+
+        assert_eq!(report.width, 120);
+        assert_eq!(report.gamma(), u128::MAX >> 68 << 60);
+        assert_eq!(report.epsilon(), u128::MAX >> 68);
+        assert_eq!(part1(&report), None);
+
+        let report = input_generator(&"1".repeat(128));
+        assert_eq!(report.gamma(), u128::MAX);
+        assert_eq!(report.epsilon(), 0);
+    }
+
+    #[test]
+    fn bit_criteria() {
+        let report = input_generator(EXAMPLE);
+
+        assert_eq!(report.life_support_ratings(), (Some(23), Some(10)));
+        assert_eq!(
+            report.filter_by_bit_criteria(|count| count.ones >= count.zeros),
+            Some(23)
+        );
+        assert_eq!(report.filter_by_bit_criteria(|_| true), Some(0b11110));
+        assert_eq!(report.filter_by_bit_criteria(|_| false), Some(0b00010));
+        assert_eq!(
+            input_generator("00\n01").filter_by_bit_criteria(|count| count.ones == 0),
+            None
         );
     }
 }
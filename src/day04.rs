@@ -1,146 +1,399 @@
 use aoc_runner_derive::{aoc, aoc_generator};
+use bitvec::prelude::*;
 use itertools::Itertools;
-use std::collections::HashSet;
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    fmt,
+};
 
-static MARKED: u8 = 255;
-
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 struct Board {
-    by_rows: [u8; 25],
-    by_cols: [u8; 25],
+    size: usize,
+    numbers: Vec<u32>,
 }
 
-fn transpose(rows: [u8; 25]) -> [u8; 25] {
-    let mut cols = <[u8; 25]>::default();
-
-    for i in 0..5 {
-        for j in 0..5 {
-            cols[j + i * 5] = rows[i + j * 5];
-        }
-    }
-
-    cols
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    board: usize,
+    row: usize,
+    col: usize,
 }
 
-impl Board {
-    fn from_rows(by_rows: [u8; 25]) -> Self {
-        Board {
-            by_rows,
-            by_cols: transpose(by_rows),
-        }
-    }
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rules {
+    pub diagonals: bool,
 }
 
 #[derive(Debug)]
 pub struct Game {
-    drawn: Vec<u8>,
+    drawn: Vec<u32>,
     boards: Vec<Board>,
+    index: HashMap<u32, Vec<Cell>>,
 }
 
 impl Board {
-    fn mark(&mut self, drawn: u8) {
-        self.by_rows = self.by_rows.map(|n| if n == drawn { MARKED } else { n });
-        self.by_cols = self.by_cols.map(|n| if n == drawn { MARKED } else { n });
+    fn new(size: usize, numbers: Vec<u32>) -> Self {
+        assert_eq!(numbers.len(), size * size, "board is not {0}x{0}", size);
+
+        Board { size, numbers }
+    }
+
+    fn lines(&self, rules: Rules) -> Vec<BTreeSet<u32>> {
+        let n = self.size;
+        let at = |row: usize, col: usize| self.numbers[row * n + col];
+
+        let mut lines = (0..n)
+            .map(|row| (0..n).map(|col| at(row, col)).collect())
+            .chain((0..n).map(|col| (0..n).map(|row| at(row, col)).collect()))
+            .collect_vec();
+
+        if rules.diagonals {
+            lines.push((0..n).map(|i| at(i, i)).collect());
+            lines.push((0..n).map(|i| at(i, n - 1 - i)).collect());
+        }
+
+        lines
     }
+}
+
+fn wins_within(lines: &[BTreeSet<u32>], drawn: &BTreeSet<u32>) -> bool {
+    lines.iter().any(|line| line.is_subset(drawn))
+}
+
+#[derive(Debug, Clone)]
+struct BoardState {
+    marked: BitVec,
+    rows: Vec<usize>,
+    cols: Vec<usize>,
+    diagonals: [usize; 2],
+    unmarked: u128,
+    won: bool,
+}
 
-    fn has_won(&self) -> bool {
-        self.by_rows
-            .chunks(5)
-            .any(|row| row.iter().all(|n| *n == MARKED))
-            || self
-                .by_cols
-                .chunks(5)
-                .any(|col| col.iter().all(|n| *n == MARKED))
+impl BoardState {
+    fn new(board: &Board) -> Self {
+        Self {
+            marked: bitvec![0; board.numbers.len()],
+            rows: vec![0; board.size],
+            cols: vec![0; board.size],
+            diagonals: [0; 2],
+            unmarked: board.numbers.iter().copied().map(u128::from).sum(),
+            won: false,
+        }
     }
 
-    fn sum_unmarked(&self) -> u32 {
-        self.by_rows
-            .iter()
-            .filter_map(|n| {
-                if *n == MARKED {
-                    None
-                } else {
-                    Some(u32::from(*n))
-                }
-            })
-            .sum()
+    fn mark(&mut self, board: &Board, Cell { row, col, .. }: Cell, rules: Rules) -> bool {
+        let n = board.size;
+        let i = row * n + col;
+
+        if self.marked[i] {
+            return false;
+        }
+
+        self.marked.set(i, true);
+        self.unmarked -= u128::from(board.numbers[i]);
+        self.rows[row] += 1;
+        self.cols[col] += 1;
+
+        let mut diagonal = false;
+        if row == col {
+            self.diagonals[0] += 1;
+            diagonal |= self.diagonals[0] == n;
+        }
+        if row + col == n - 1 {
+            self.diagonals[1] += 1;
+            diagonal |= self.diagonals[1] == n;
+        }
+
+        self.rows[row] == n || self.cols[col] == n || rules.diagonals && diagonal
     }
 }
 
 #[aoc_generator(day4)]
 pub fn input_generator(input: &str) -> Game {
-    let mut lines = input.lines();
-    let drawn = lines
+    let mut sections = input.trim().split("\n\n");
+    let drawn = sections
         .next()
         .unwrap()
         .split(',')
-        .map(|s| s.parse().unwrap())
+        .map(|s| s.trim().parse().unwrap())
         .collect_vec();
 
-    let boards = lines
-        .chunks(6)
-        .into_iter()
-        .map(|lines| {
-            Board::from_rows(
-                lines
-                    .skip(1)
+    let boards = sections
+        .map(|section| {
+            let rows = section.lines().collect_vec();
+
+            Board::new(
+                rows.len(),
+                rows.iter()
                     .flat_map(|line| line.split_whitespace().map(|s| s.parse().unwrap()))
-                    .collect::<Vec<_>>()
-                    .try_into()
-                    .unwrap(),
+                    .collect(),
             )
         })
         .collect_vec();
 
-    Game { drawn, boards }
+    Game::new(drawn, boards)
 }
 
-#[aoc(day4, part1)]
-pub fn part1(input: &Game) -> u32 {
-    let mut boards = input.boards.clone();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Win {
+    pub board: usize,
+    pub draw: usize,
+    pub number: u32,
+    pub score: u128,
+}
 
-    for drawn in &input.drawn {
-        for board in &mut boards {
-            board.mark(*drawn);
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub ranking: Vec<Win>,
+    pub never_won: Vec<usize>,
+}
 
-            if board.has_won() {
-                return board.sum_unmarked() * u32::from(*drawn);
+impl Game {
+    fn new(drawn: Vec<u32>, boards: Vec<Board>) -> Self {
+        let mut index = HashMap::<_, Vec<_>>::new();
+
+        for (idx, board) in boards.iter().enumerate() {
+            for (i, &number) in board.numbers.iter().enumerate() {
+                index.entry(number).or_default().push(Cell {
+                    board: idx,
+                    row: i / board.size,
+                    col: i % board.size,
+                });
             }
         }
+
+        Self {
+            drawn,
+            boards,
+            index,
+        }
     }
 
-    unreachable!()
-}
+    pub fn with_draws(&self, drawn: Vec<u32>) -> Self {
+        Self {
+            drawn,
+            boards: self.boards.clone(),
+            index: self.index.clone(),
+        }
+    }
 
-#[aoc(day4, part2)]
-pub fn part2(input: &Game) -> u32 {
-    let mut boards = input.boards.clone();
+    pub fn play(&self) -> Outcome {
+        self.play_with(Rules::default())
+    }
+
+    pub fn play_with(&self, rules: Rules) -> Outcome {
+        let mut states = self.boards.iter().map(BoardState::new).collect_vec();
+        let mut ranking = Vec::new();
 
-    let mut won = HashSet::new();
-    let mut last_won_with_drawn = None;
+        for (draw, &number) in self.drawn.iter().enumerate() {
+            let cells = self.index.get(&number).into_iter().flatten();
 
-    for drawn in &input.drawn {
-        for (idx, board) in boards.iter_mut().enumerate() {
-            if won.contains(&idx) {
-                continue;
+            for (idx, cells) in &cells.group_by(|cell| cell.board) {
+                let (board, state) = (&self.boards[idx], &mut states[idx]);
+                if state.won {
+                    continue;
+                }
+
+                let mut completed = false;
+                for &cell in cells {
+                    completed |= state.mark(board, cell, rules);
+                }
+
+                if completed {
+                    state.won = true;
+                    ranking.push(Win {
+                        board: idx,
+                        draw,
+                        number,
+                        score: state.unmarked * u128::from(number),
+                    });
+                }
             }
+        }
 
-            board.mark(*drawn);
+        Outcome {
+            ranking,
+            never_won: states.iter().positions(|state| !state.won).collect(),
+        }
+    }
+}
 
-            if board.has_won() {
-                won.insert(idx);
-                last_won_with_drawn = Some((*board, *drawn));
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Goal {
+    First,
+    Last,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RigError {
+    NoSuchBoard(usize),
+    Unwinnable { board: usize, goal: Goal },
+}
+
+impl fmt::Display for RigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RigError::NoSuchBoard(board) => write!(f, "there is no board {}", board),
+            RigError::Unwinnable { board, goal } => {
+                let goal = match goal {
+                    Goal::First => "first",
+                    Goal::Last => "last",
+                };
+
+                write!(f, "no draw order makes board {} win {}", board, goal)
             }
         }
     }
+}
+
+impl Error for RigError {}
+
+impl Game {
+    pub fn rig(&self, target: usize, goal: Goal, rules: Rules) -> Result<Vec<u32>, RigError> {
+        if target >= self.boards.len() {
+            return Err(RigError::NoSuchBoard(target));
+        }
+
+        let lines = self
+            .boards
+            .iter()
+            .map(|board| board.lines(rules))
+            .collect_vec();
+
+        let sequence = match goal {
+            Goal::First => Self::rig_first(target, &lines),
+            Goal::Last => Self::rig_last(target, &lines),
+        };
+
+        sequence.ok_or(RigError::Unwinnable {
+            board: target,
+            goal,
+        })
+    }
+
+    fn rig_first(target: usize, lines: &[Vec<BTreeSet<u32>>]) -> Option<Vec<u32>> {
+        lines[target]
+            .iter()
+            .sorted_by_key(|line| line.len())
+            .flat_map(|line| line.iter().map(move |&last| (line, last)))
+            .find_map(|(line, last)| {
+                let mut before = line.clone();
+                before.remove(&last);
+
+                let clear = lines.iter().enumerate().all(|(idx, lines)| {
+                    !wins_within(lines, if idx < target { line } else { &before })
+                });
+
+                clear.then(|| before.into_iter().chain([last]).collect())
+            })
+    }
 
-    let (board, drawn) = last_won_with_drawn.unwrap();
-    board.sum_unmarked() * u32::from(drawn)
+    // Finding the shortest order means choosing one line per board, which is
+    // exponential in the board count, so every other board first greedily gets
+    // whichever line adds the fewest new numbers. Only if that finds nothing
+    // are the choices searched exhaustively.
+    fn rig_last(target: usize, lines: &[Vec<BTreeSet<u32>>]) -> Option<Vec<u32>> {
+        let riggings = lines[target]
+            .iter()
+            .flat_map(|line| line.iter().map(move |&last| (line, last)))
+            .filter_map(|(line, last)| {
+                let mut drawn = line.clone();
+                drawn.remove(&last);
+
+                let rigging = Rigging {
+                    target,
+                    last,
+                    lines,
+                };
+                (!wins_within(&lines[target], &drawn)).then_some((rigging, drawn))
+            })
+            .collect_vec();
+
+        let search = |backtrack| {
+            riggings.iter().filter_map(move |(rigging, drawn)| {
+                let drawn = rigging.search(drawn.clone(), 0, backtrack)?;
+                Some(drawn.into_iter().chain([rigging.last]).collect_vec())
+            })
+        };
+
+        search(false)
+            .min_by_key(|sequence| sequence.len())
+            .or_else(|| search(true).next())
+    }
+}
+
+struct Rigging<'a> {
+    target: usize,
+    last: u32,
+    lines: &'a [Vec<BTreeSet<u32>>],
+}
+
+impl Rigging<'_> {
+    // Boards ranked ahead of the target may complete on the final number.
+    fn has_won(&self, idx: usize, drawn: &BTreeSet<u32>) -> bool {
+        self.lines[idx].iter().any(|line| {
+            line.iter()
+                .all(|n| drawn.contains(n) || idx < self.target && *n == self.last)
+        })
+    }
+
+    fn options<'b>(
+        &'b self,
+        idx: usize,
+        drawn: &'b BTreeSet<u32>,
+    ) -> impl Iterator<Item = BTreeSet<u32>> + 'b {
+        self.lines[idx]
+            .iter()
+            .filter(move |line| idx < self.target || !line.contains(&self.last))
+            .map(move |line| {
+                let mut next = drawn.clone();
+                next.extend(line.iter().filter(|&&number| number != self.last));
+                next
+            })
+            .filter(move |next| !wins_within(&self.lines[self.target], next))
+    }
+
+    // Boards before `from` have already won, since `drawn` only grows.
+    fn search(&self, drawn: BTreeSet<u32>, from: usize, backtrack: bool) -> Option<BTreeSet<u32>> {
+        let mut pending = (from..self.lines.len())
+            .filter(|&idx| idx != self.target && !self.has_won(idx, &drawn));
+
+        let idx = match pending.next() {
+            Some(idx) => idx,
+            None => return Some(drawn),
+        };
+
+        if !backtrack {
+            let next = self.options(idx, &drawn).min_by_key(|next| next.len())?;
+            return self.search(next, idx + 1, false);
+        }
+
+        if pending.any(|idx| self.options(idx, &drawn).next().is_none()) {
+            return None;
+        }
+
+        self.options(idx, &drawn)
+            .sorted_by_key(|next| next.len())
+            .find_map(|next| self.search(next, idx + 1, true))
+    }
+}
+
+#[aoc(day4, part1)]
+pub fn part1(input: &Game) -> Option<u128> {
+    input.play().ranking.first().map(|win| win.score)
+}
+
+#[aoc(day4, part2)]
+pub fn part2(input: &Game) -> Option<u128> {
+    input.play().ranking.last().map(|win| win.score)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::Lcg;
 
     const EXAMPLE: &str = "7,4,9,5,11,17,23,2,0,14,21,24,10,16,13,6,15,25,12,22,18,20,8,19,3,26,1
 
@@ -164,27 +417,322 @@ mod tests {
 
     #[test]
     fn example1() {
-        assert_eq!(part1(&input_generator(EXAMPLE)), 4_512);
+        assert_eq!(part1(&input_generator(EXAMPLE)), Some(4_512));
     }
 
     #[test]
     fn solution1() {
         assert_eq!(
             part1(&input_generator(include_str!("../input/2021/day4.txt"))),
-            27_027,
+            Some(27_027),
         );
     }
 
     #[test]
     fn example2() {
-        assert_eq!(part2(&input_generator(EXAMPLE)), 1_924);
+        assert_eq!(part2(&input_generator(EXAMPLE)), Some(1_924));
     }
 
     #[test]
     fn solution2() {
         assert_eq!(
             part2(&input_generator(include_str!("../input/2021/day4.txt"))),
-            36_975,
+            Some(36_975),
+        );
+    }
+
+    #[test]
+    fn play() {
+        let game = input_generator(EXAMPLE);
+
+        assert_eq!(
+            game.play(),
+            Outcome {
+                ranking: vec![
+                    Win {
+                        board: 2,
+                        draw: 11,
+                        number: 24,
+                        score: 4_512,
+                    },
+                    Win {
+                        board: 0,
+                        draw: 13,
+                        number: 16,
+                        score: 2_192,
+                    },
+                    Win {
+                        board: 1,
+                        draw: 14,
+                        number: 13,
+                        score: 1_924,
+                    },
+                ],
+                never_won: vec![],
+            }
+        );
+
+        let game = input_generator(&EXAMPLE.replacen(",13,6,15,25,12,22,18,20,8,19,3,26,1", "", 1));
+        let outcome = game.play();
+        assert_eq!(outcome.ranking.len(), 2);
+        assert_eq!(
+            game.with_draws(vec![22, 13, 17, 11, 0]).play().ranking[0].board,
+            0
+        );
+        assert_eq!(outcome.never_won, [1]);
+    }
+
+    #[test]
+    fn board_sizes_and_large_numbers() {
+        let game = input_generator(
+            "300,255,7,1000
+
+255 300
+  1   2
+
+  7 255
+300   9
+
+  4   5   6
+  7 255   8
+  1 300   2",
+        );
+
+        assert_eq!(
+            game.play().ranking,
+            [
+                Win {
+                    board: 0,
+                    draw: 1,
+                    number: 255,
+                    score: 3 * 255,
+                },
+                Win {
+                    board: 1,
+                    draw: 2,
+                    number: 7,
+                    score: 9 * 7,
+                },
+            ]
+        );
+        assert_eq!(game.play().never_won, [2]);
+
+        let game = input_generator("1,100000\n\n1 100000\n99999 99999");
+        assert_eq!(part1(&game), Some(2 * 99_999 * 100_000));
+
+        let game = input_generator("4000000000\n\n4000000000 4000000000\n3999999999 3999999999");
+        assert_eq!(part1(&game), Some(2 * 3_999_999_999 * 4_000_000_000));
+    }
+
+    #[test]
+    fn diagonals() {
+        let game = input_generator(
+            "1,5,9,3,7
+
+1 2 3
+4 5 6
+7 8 9",
+        );
+
+        assert_eq!(game.play().ranking, []);
+        assert_eq!(
+            game.play_with(Rules { diagonals: true }).ranking,
+            [Win {
+                board: 0,
+                draw: 2,
+                number: 9,
+                score: (2 + 3 + 4 + 6 + 7 + 8) * 9,
+            }]
+        );
+
+        let game = input_generator(
+            "3,5,7
+
+1 2 3
+4 5 6
+7 8 9",
+        );
+        assert_eq!(
+            game.play_with(Rules { diagonals: true }).ranking[0].number,
+            7
+        );
+    }
+
+    fn naive_ranking(game: &Game) -> Vec<(usize, usize, u128)> {
+        let mut marked = vec![vec![false; 25]; game.boards.len()];
+        let mut won = vec![false; game.boards.len()];
+        let mut ranking = vec![];
+
+        for (draw, &number) in game.drawn.iter().enumerate() {
+            for (idx, board) in game.boards.iter().enumerate() {
+                if won[idx] {
+                    continue;
+                }
+
+                for (i, &n) in board.numbers.iter().enumerate() {
+                    marked[idx][i] |= n == number;
+                }
+
+                let m = &marked[idx];
+                if (0..5).any(|r| (0..5).all(|c| m[r * 5 + c]))
+                    || (0..5).any(|c| (0..5).all(|r| m[r * 5 + c]))
+                {
+                    won[idx] = true;
+                    let unmarked: u32 = (0..25).filter(|&i| !m[i]).map(|i| board.numbers[i]).sum();
+                    ranking.push((idx, draw, u128::from(unmarked * number)));
+                }
+            }
+        }
+
+        ranking
+    }
+
+    #[test]
+    fn many_boards() {
+        let mut rng = Lcg::new(0x2021);
+
+        let drawn = (0..500).map(|_| rng.below(400) as u32).collect_vec();
+        let boards = (0..2_000)
+            .map(|_| Board::new(5, (0..25).map(|_| rng.below(400) as u32).collect()))
+            .collect_vec();
+        let game = Game::new(drawn, boards);
+
+        let outcome = game.play();
+        assert_eq!(
+            outcome
+                .ranking
+                .iter()
+                .map(|win| (win.board, win.draw, win.score))
+                .collect_vec(),
+            naive_ranking(&game)
+        );
+        assert_eq!(outcome.ranking.len() + outcome.never_won.len(), 2_000);
+    }
+
+    fn replay(game: &Game, drawn: &[u32], rules: Rules) -> Outcome {
+        game.with_draws(drawn.to_vec()).play_with(rules)
+    }
+
+    #[test]
+    fn rig_first() {
+        let game = input_generator(EXAMPLE);
+
+        for target in 0..3 {
+            let drawn = game.rig(target, Goal::First, Rules::default()).unwrap();
+            let outcome = replay(&game, &drawn, Rules::default());
+
+            assert_eq!(drawn.len(), 5);
+            assert_eq!(outcome.ranking[0].board, target);
+            assert_eq!(outcome.ranking[0].draw, drawn.len() - 1);
+        }
+
+        let game = input_generator("1\n\n1 2\n3 4\n\n1 1\n1 1\n\n1 5\n1 1");
+        assert_eq!(game.rig(0, Goal::First, Rules::default()), Ok(vec![2, 1]));
+        assert_eq!(game.rig(1, Goal::First, Rules::default()), Ok(vec![1]));
+        assert_eq!(
+            game.rig(2, Goal::First, Rules { diagonals: true }),
+            Err(RigError::Unwinnable {
+                board: 2,
+                goal: Goal::First,
+            })
+        );
+        assert_eq!(
+            game.rig(2, Goal::First, Rules::default()),
+            Err(RigError::Unwinnable {
+                board: 2,
+                goal: Goal::First,
+            })
+        );
+    }
+
+    fn assert_wins_last(game: &Game, target: usize, drawn: &[u32]) {
+        let outcome = replay(game, drawn, Rules::default());
+
+        assert_eq!(outcome.never_won, []);
+        assert_eq!(outcome.ranking.last().unwrap().board, target);
+        assert_eq!(outcome.ranking.last().unwrap().draw, drawn.len() - 1);
+    }
+
+    #[test]
+    fn rig_last() {
+        let game = input_generator(EXAMPLE);
+
+        for target in 0..3 {
+            let drawn = game.rig(target, Goal::Last, Rules::default()).unwrap();
+            assert_wins_last(&game, target, &drawn);
+        }
+
+        let game = input_generator("1\n\n1 2\n3 4\n\n1 1\n1 1");
+        assert_eq!(game.rig(0, Goal::Last, Rules::default()), Ok(vec![1, 2]));
+
+        let game = input_generator("1\n\n1 2\n1 3\n\n1 1\n1 1");
+        assert_eq!(
+            game.rig(0, Goal::Last, Rules::default()),
+            Err(RigError::Unwinnable {
+                board: 0,
+                goal: Goal::Last,
+            })
+        );
+        assert_eq!(game.rig(1, Goal::Last, Rules::default()), Ok(vec![1]));
+
+        let game = input_generator("1\n\n1 2\n3 4\n\n5 6\n7 1");
+        let rules = Rules { diagonals: true };
+        let drawn = game.rig(0, Goal::Last, rules).unwrap();
+        assert_eq!(drawn.len(), 3);
+        assert_eq!(
+            replay(&game, &drawn, rules).ranking.last().unwrap().board,
+            0
+        );
+    }
+
+    #[test]
+    fn rig_last_backtracks() {
+        let game = input_generator(
+            "1,2,3,4,5
+
+4 6 2
+4 6 2
+1 6 4
+
+6 2 3
+6 2 2
+2 4 6
+
+3 5 6
+5 3 1
+4 6 2
+
+2 6 5
+2 2 5
+5 5 4
+
+6 5 6
+2 6 2
+5 5 5",
+        );
+        assert_wins_last(&game, 4, &[1, 2, 3, 4, 5]);
+
+        let drawn = game.rig(4, Goal::Last, Rules::default()).unwrap();
+        assert_wins_last(&game, 4, &drawn);
+    }
+
+    #[test]
+    fn rig_solution() {
+        let game = input_generator(include_str!("../input/2021/day4.txt"));
+
+        for target in [0, 3, 50, 99] {
+            let drawn = game.rig(target, Goal::First, Rules::default()).unwrap();
+            let outcome = replay(&game, &drawn, Rules::default());
+            assert_eq!(drawn.len(), 5);
+            assert_eq!(outcome.ranking[0].board, target);
+
+            let drawn = game.rig(target, Goal::Last, Rules::default()).unwrap();
+            assert_wins_last(&game, target, &drawn);
+        }
+
+        assert_eq!(
+            game.rig(100, Goal::First, Rules::default()),
+            Err(RigError::NoSuchBoard(100))
         );
     }
 }
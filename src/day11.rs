@@ -1,6 +1,15 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::iproduct;
 use ndarray::prelude::*;
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    io::{self, Write},
+    num::NonZeroU32,
+    thread,
+    time::Duration,
+};
 
 type Grid = Array2<u8>;
 
@@ -18,79 +27,260 @@ pub fn input_generator(input: &str) -> Grid {
     .unwrap()
 }
 
-fn step(mut grid: Grid) -> (usize, Grid) {
-    fn inc(energy: &mut u8) {
-        *energy += 1;
-    }
+type Pos = (usize, usize);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub grid: Grid,
+    pub flashed: Vec<Pos>,
+    pub depth: usize,
+}
 
-    grid += 1;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycle {
+    pub start: usize,
+    pub period: usize,
+}
 
-    let mut flashed = Array2::from_elem((grid.ncols(), grid.nrows()), false);
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "octopusses never synchronise: grid at step {} repeats every {} steps",
+            self.start, self.period
+        )
+    }
+}
 
-    loop {
-        let mut should_stop = true;
+impl Error for Cycle {}
 
-        for (h, w) in iproduct!(0..grid.ncols(), 0..grid.nrows()) {
-            if grid[(h, w)] > 9 && !flashed[(h, w)] {
-                flashed[(h, w)] = true;
-                should_stop = false;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Neighbourhood {
+    VonNeumann,
+    Moore,
+    Hex,
+}
 
-                grid.get_mut((h.wrapping_sub(1), w.wrapping_sub(1)))
-                    .map(inc);
-                grid.get_mut((h.wrapping_sub(1), w)).map(inc);
-                grid.get_mut((h.wrapping_sub(1), w + 1)).map(inc);
-                grid.get_mut((h, w.wrapping_sub(1))).map(inc);
-                grid.get_mut((h, w + 1)).map(inc);
-                grid.get_mut((h + 1, w.wrapping_sub(1))).map(inc);
-                grid.get_mut((h + 1, w)).map(inc);
-                grid.get_mut((h + 1, w + 1)).map(inc);
+impl Neighbourhood {
+    fn offsets(self, row: usize) -> &'static [(isize, isize)] {
+        match self {
+            Neighbourhood::VonNeumann => &[(-1, 0), (0, -1), (0, 1), (1, 0)],
+            Neighbourhood::Moore => &[
+                (-1, -1),
+                (-1, 0),
+                (-1, 1),
+                (0, -1),
+                (0, 1),
+                (1, -1),
+                (1, 0),
+                (1, 1),
+            ],
+            Neighbourhood::Hex if row.is_multiple_of(2) => {
+                &[(-1, -1), (-1, 0), (0, -1), (0, 1), (1, -1), (1, 0)]
             }
+            Neighbourhood::Hex => &[(-1, 0), (-1, 1), (0, -1), (0, 1), (1, 0), (1, 1)],
         }
+    }
 
-        if should_stop {
-            break;
+    fn neighbours(self, (h, w): Pos) -> impl Iterator<Item = Pos> {
+        self.offsets(h)
+            .iter()
+            .map(move |&(dh, dw)| (h.wrapping_add(dh as usize), w.wrapping_add(dw as usize)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rules {
+    pub threshold: u8,
+    pub reset: u8,
+    pub neighbourhood: Neighbourhood,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            threshold: 9,
+            reset: 0,
+            neighbourhood: Neighbourhood::Moore,
         }
     }
+}
+
+fn cascade(mut grid: Grid, rules: &Rules) -> Step {
+    grid.mapv_inplace(|energy| energy.saturating_add(1));
+
+    let (height, width) = grid.dim();
+    let mut flashed = Vec::new();
+    let mut wave = iproduct!(0..height, 0..width)
+        .filter(|&pos| grid[pos] > rules.threshold)
+        .collect::<Vec<_>>();
+    let mut depth = 0;
 
-    grid.map_inplace(|energy| {
-        if *energy > 9 {
-            *energy = 0
+    while !wave.is_empty() {
+        depth += 1;
+
+        let mut next_wave = Vec::new();
+        for &pos in &wave {
+            for n in rules.neighbourhood.neighbours(pos) {
+                if let Some(energy) = grid.get_mut(n) {
+                    if *energy == rules.threshold {
+                        next_wave.push(n);
+                    }
+                    *energy = energy.saturating_add(1);
+                }
+            }
         }
-    });
 
-    (flashed.iter().filter(|&&flashed| flashed).count(), grid)
+        flashed.append(&mut wave);
+        next_wave.sort_unstable();
+        wave = next_wave;
+    }
+
+    for &pos in &flashed {
+        grid[pos] = rules.reset;
+    }
+
+    Step {
+        grid,
+        flashed,
+        depth,
+    }
 }
 
-#[aoc(day11, part1)]
-pub fn part1(grid: &Grid) -> usize {
-    (0..100)
-        .fold((0_usize, grid.clone()), |(acc, grid), _| {
-            let (inc, next_grid) = step(grid);
-            (acc + inc, next_grid)
-        })
-        .0
+pub struct OctopusSim {
+    grid: Grid,
+    rules: Rules,
 }
 
-#[aoc(day11, part2)]
-pub fn part2(grid: &Grid) -> usize {
-    let mut i = 1;
+impl OctopusSim {
+    pub fn new(grid: Grid) -> Self {
+        Self::with_rules(grid, Rules::default())
+    }
+
+    pub fn with_rules(grid: Grid, rules: Rules) -> Self {
+        Self { grid, rules }
+    }
+
+    pub fn first_sync(self) -> Result<usize, Cycle> {
+        let octopusses = self.grid.len();
+        let mut seen = HashMap::new();
+        seen.insert(self.grid.clone(), 0);
 
-    let octopusses = grid.ncols() * grid.nrows();
-    let mut grid = grid.clone();
+        for (i, step) in self.enumerate().map(|(i, step)| (i + 1, step)) {
+            if step.flashed.len() == octopusses {
+                return Ok(i);
+            }
 
-    loop {
-        let (flashes, next_grid) = step(grid);
-        if flashes == octopusses {
-            return i;
+            if let Some(start) = seen.insert(step.grid, i) {
+                return Err(Cycle {
+                    start,
+                    period: i - start,
+                });
+            }
         }
-        i += 1;
-        grid = next_grid;
+
+        unreachable!()
+    }
+}
+
+impl Iterator for OctopusSim {
+    type Item = Step;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let step = cascade(self.grid.clone(), &self.rules);
+        self.grid = step.grid.clone();
+        Some(step)
+    }
+}
+
+const CLEAR: &str = "\x1b[2J\x1b[H";
+const BRIGHT: &str = "\x1b[1;97m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+pub fn render_frame(step: &Step, ansi: bool) -> String {
+    let mut flashed = Array2::from_elem(step.grid.dim(), false);
+    for &pos in &step.flashed {
+        flashed[pos] = true;
+    }
+
+    let mut frame = String::new();
+    for (energies, flashes) in step.grid.rows().into_iter().zip(flashed.rows()) {
+        for (&energy, &flash) in energies.iter().zip(flashes) {
+            let c = char::from_digit(energy.into(), 36).unwrap_or('*');
+            match (ansi, flash) {
+                (true, true) => frame.extend([BRIGHT, &c.to_string(), RESET]),
+                (true, false) => frame.extend([DIM, &c.to_string(), RESET]),
+                (false, _) => frame.push(c),
+            }
+        }
+        frame.push('\n');
     }
+
+    frame
+}
+
+pub fn play<W: Write>(
+    steps: impl IntoIterator<Item = Step>,
+    fps: NonZeroU32,
+    out: &mut W,
+) -> io::Result<()> {
+    let frame_time = Duration::from_secs(1) / fps.get();
+
+    for (i, step) in steps.into_iter().enumerate() {
+        write!(
+            out,
+            "{}step {} ({} flashes, depth {})\n{}",
+            CLEAR,
+            i + 1,
+            step.flashed.len(),
+            step.depth,
+            render_frame(&step, true)
+        )?;
+        out.flush()?;
+        thread::sleep(frame_time);
+    }
+
+    Ok(())
+}
+
+pub fn record<W: Write>(steps: impl IntoIterator<Item = Step>, out: &mut W) -> io::Result<()> {
+    for (i, step) in steps.into_iter().enumerate() {
+        writeln!(
+            out,
+            "step {} ({} flashes, depth {})\n{}",
+            i + 1,
+            step.flashed.len(),
+            step.depth,
+            render_frame(&step, false)
+        )?;
+    }
+
+    Ok(())
+}
+
+#[aoc(day11, part1)]
+pub fn part1(grid: &Grid) -> usize {
+    OctopusSim::new(grid.clone())
+        .take(100)
+        .map(|step| step.flashed.len())
+        .sum()
+}
+
+#[aoc(day11, part2)]
+pub fn part2(grid: &Grid) -> Result<usize, Cycle> {
+    OctopusSim::new(grid.clone()).first_sync()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use itertools::Itertools;
+
+    fn step(grid: Grid) -> (usize, Grid) {
+        let Step { grid, flashed, .. } = cascade(grid, &Rules::default());
+        (flashed.len(), grid)
+    }
 
     const EXAMPLE: &str = "5483143223
 2745854711
@@ -194,14 +384,171 @@ mod tests {
 
     #[test]
     fn example2() {
-        assert_eq!(part2(&input_generator(EXAMPLE)), 195);
+        assert_eq!(part2(&input_generator(EXAMPLE)), Ok(195));
     }
 
     #[test]
     fn solution2() {
         assert_eq!(
             part2(&input_generator(include_str!("../input/2021/day11.txt"))),
-            471,
+            Ok(471),
+        );
+    }
+
+    #[test]
+    fn sim_flash_order_and_depth() {
+        let mut sim = OctopusSim::new(input_generator(
+            "11111
+19991
+19191
+19991
+11111",
+        ));
+
+        let first = sim.next().unwrap();
+        assert_eq!(first.depth, 2);
+        assert_eq!(
+            first.flashed,
+            vec![
+                (1, 1),
+                (1, 2),
+                (1, 3),
+                (2, 1),
+                (2, 3),
+                (3, 1),
+                (3, 2),
+                (3, 3),
+                (2, 2),
+            ]
+        );
+
+        let second = sim.next().unwrap();
+        assert_eq!(second.depth, 0);
+        assert!(second.flashed.is_empty());
+    }
+
+    #[test]
+    fn sim_cycle() {
+        let sim = OctopusSim::new(input_generator("00"));
+        assert_eq!(sim.first_sync(), Ok(10));
+
+        let sim = OctopusSim::new(input_generator("0013"));
+        assert_eq!(
+            sim.first_sync(),
+            Err(Cycle {
+                start: 16,
+                period: 9
+            })
+        );
+    }
+
+    #[test]
+    fn rectangular() {
+        let grid = input_generator(&EXAMPLE.lines().take(4).join("\n"));
+        assert_eq!(grid.dim(), (4, 10));
+
+        let transposed = grid.t().to_owned();
+        for (step, transposed_step) in OctopusSim::new(grid)
+            .zip(OctopusSim::new(transposed))
+            .take(100)
+        {
+            assert_eq!(step.grid.t(), transposed_step.grid);
+            assert_eq!(step.flashed.len(), transposed_step.flashed.len());
+        }
+    }
+
+    #[test]
+    fn neighbourhoods() {
+        let grid = input_generator(
+            "000
+090
+000",
+        );
+
+        let step = |neighbourhood| {
+            let rules = Rules {
+                neighbourhood,
+                ..Rules::default()
+            };
+            OctopusSim::with_rules(grid.clone(), rules)
+                .next()
+                .unwrap()
+                .grid
+        };
+
+        assert_eq!(
+            step(Neighbourhood::VonNeumann),
+            input_generator(
+                "121
+202
+121"
+            )
+        );
+        assert_eq!(
+            step(Neighbourhood::Moore),
+            input_generator(
+                "222
+202
+222"
+            )
+        );
+        assert_eq!(
+            step(Neighbourhood::Hex),
+            input_generator(
+                "122
+202
+122"
+            )
+        );
+    }
+
+    #[test]
+    fn threshold_and_reset() {
+        let rules = Rules {
+            threshold: 3,
+            reset: 1,
+            ..Rules::default()
+        };
+        let mut sim = OctopusSim::with_rules(input_generator("23\n00"), rules);
+
+        let step = sim.next().unwrap();
+        assert_eq!(step.flashed, vec![(0, 1), (0, 0)]);
+        assert_eq!(step.depth, 2);
+        assert_eq!(step.grid, input_generator("11\n33"));
+
+        let step = sim.next().unwrap();
+        assert_eq!(step.flashed, vec![(1, 0), (1, 1), (0, 0), (0, 1)]);
+        assert_eq!(step.depth, 2);
+        assert_eq!(step.grid, input_generator("11\n11"));
+    }
+
+    #[test]
+    fn render() {
+        let step = OctopusSim::new(input_generator("19\n11")).next().unwrap();
+
+        assert_eq!(render_frame(&step, false), "30\n33\n");
+        assert_eq!(
+            render_frame(&step, true),
+            "\x1b[2m3\x1b[0m\x1b[1;97m0\x1b[0m\n\x1b[2m3\x1b[0m\x1b[2m3\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn record_() {
+        let mut out = Vec::new();
+        record(OctopusSim::new(input_generator("19\n11")).take(2), &mut out).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "step 1 (1 flashes, depth 1)
+30
+33
+
+step 2 (0 flashes, depth 0)
+41
+44
+
+"
         );
     }
 }
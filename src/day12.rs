@@ -1,6 +1,11 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
-use std::{collections::BTreeMap, str::FromStr};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    error::Error,
+    fmt,
+    str::FromStr,
+};
 
 #[derive(Debug, Clone, PartialOrd, Ord, PartialEq, Eq)]
 pub enum Cave {
@@ -14,6 +19,20 @@ impl Cave {
     fn is_small(&self) -> bool {
         matches!(self, Cave::Small(_))
     }
+
+    fn is_big(&self) -> bool {
+        matches!(self, Cave::Big(_))
+    }
+}
+
+impl fmt::Display for Cave {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cave::Start => write!(f, "start"),
+            Cave::End => write!(f, "end"),
+            Cave::Small(name) | Cave::Big(name) => write!(f, "{}", name),
+        }
+    }
 }
 
 impl FromStr for Cave {
@@ -36,13 +55,17 @@ impl FromStr for Cave {
 
 pub type Nodes = BTreeMap<Cave, Vec<Cave>>;
 
-trait Path<'a>: Clone {
+pub trait Path<'a>: Clone {
     fn visit(&mut self, cave: &'a Cave);
     fn can_visit(&self, cave: &'a Cave) -> bool;
+
+    fn accepts(&self) -> bool {
+        true
+    }
 }
 
 #[derive(Clone, Default)]
-struct Part1Path<'a>(Vec<&'a Cave>);
+pub struct Part1Path<'a>(Vec<&'a Cave>);
 
 impl<'a> Path<'a> for Part1Path<'a> {
     fn visit(&mut self, cave: &'a Cave) {
@@ -59,7 +82,7 @@ impl<'a> Path<'a> for Part1Path<'a> {
 }
 
 #[derive(Clone, Default)]
-struct Part2Path<'a> {
+pub struct Part2Path<'a> {
     any_small_cave_visited_twice: bool,
     path: Vec<&'a Cave>,
 }
@@ -88,6 +111,82 @@ impl<'a> Path<'a> for Part2Path<'a> {
     }
 }
 
+#[derive(Clone)]
+pub struct RevisitPolicy<'a> {
+    revisited_caves: usize,
+    max_visits: usize,
+    forbidden: &'a [Cave],
+    required: &'a [Cave],
+    revisited: usize,
+    path: Vec<&'a Cave>,
+}
+
+impl<'a> RevisitPolicy<'a> {
+    pub fn new(revisited_caves: usize, max_visits: usize) -> Self {
+        Self {
+            revisited_caves,
+            max_visits,
+            forbidden: &[],
+            required: &[],
+            revisited: 0,
+            path: Vec::new(),
+        }
+    }
+
+    pub fn part1() -> Self {
+        Self::new(0, 1)
+    }
+
+    pub fn part2() -> Self {
+        Self::new(1, 2)
+    }
+
+    pub fn forbid(mut self, caves: &'a [Cave]) -> Self {
+        self.forbidden = caves;
+        self
+    }
+
+    pub fn require(mut self, caves: &'a [Cave]) -> Self {
+        self.required = caves;
+        self
+    }
+
+    fn times_visited(&self, cave: &Cave) -> usize {
+        self.path.iter().filter(|&&visited| visited == cave).count()
+    }
+}
+
+impl<'a> Path<'a> for RevisitPolicy<'a> {
+    fn visit(&mut self, cave: &'a Cave) {
+        self.path.push(cave);
+
+        if cave.is_small() && self.times_visited(cave) == 2 {
+            self.revisited += 1;
+        }
+    }
+
+    fn can_visit(&self, cave: &'a Cave) -> bool {
+        if self.forbidden.contains(cave) {
+            return false;
+        }
+
+        if !cave.is_small() {
+            return true;
+        }
+
+        match self.times_visited(cave) {
+            0 => true,
+            times if times >= self.max_visits => false,
+            1 => self.revisited < self.revisited_caves,
+            _ => true,
+        }
+    }
+
+    fn accepts(&self) -> bool {
+        self.required.iter().all(|cave| self.path.contains(&cave))
+    }
+}
+
 #[aoc_generator(day12)]
 pub fn input_generator(input: &str) -> Nodes {
     input
@@ -110,31 +209,379 @@ pub fn input_generator(input: &str) -> Nodes {
         })
 }
 
-fn count_all_paths_from<'a, P>(nodes: &'a Nodes, mut path: P, current: &'a Cave) -> usize
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphError {
+    MissingStart,
+    UnreachableEnd,
+    BigCavesAdjacent(Cave, Cave),
+    IsolatedCave(Cave),
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphError::MissingStart => write!(f, "cave system has no start"),
+            GraphError::UnreachableEnd => write!(f, "end cannot be reached from start"),
+            GraphError::BigCavesAdjacent(a, b) => write!(
+                f,
+                "big caves {} and {} are adjacent, paths would be infinite",
+                a, b
+            ),
+            GraphError::IsolatedCave(cave) => {
+                write!(f, "cave {} cannot be reached from start", cave)
+            }
+        }
+    }
+}
+
+impl Error for GraphError {}
+
+pub fn validate(nodes: &Nodes) -> Result<(), GraphError> {
+    if !nodes.contains_key(&Cave::Start) {
+        return Err(GraphError::MissingStart);
+    }
+
+    for (cave, next_caves) in nodes {
+        if let Some(next_cave) = next_caves
+            .iter()
+            .find(|next_cave| cave.is_big() && next_cave.is_big())
+        {
+            return Err(GraphError::BigCavesAdjacent(
+                cave.clone(),
+                next_cave.clone(),
+            ));
+        }
+    }
+
+    let mut reachable = BTreeSet::from([&Cave::Start]);
+    let mut queue = vec![&Cave::Start];
+    while let Some(cave) = queue.pop() {
+        if cave == &Cave::End {
+            continue;
+        }
+
+        for next_cave in nodes.get(cave).into_iter().flatten() {
+            if reachable.insert(next_cave) {
+                queue.push(next_cave);
+            }
+        }
+    }
+
+    if !reachable.contains(&Cave::End) {
+        return Err(GraphError::UnreachableEnd);
+    }
+
+    match nodes
+        .iter()
+        .flat_map(|(cave, next_caves)| std::iter::once(cave).chain(next_caves))
+        .find(|cave| !reachable.contains(cave))
+    {
+        Some(cave) => Err(GraphError::IsolatedCave(cave.clone())),
+        None => Ok(()),
+    }
+}
+
+struct Graph {
+    start: usize,
+    end: usize,
+    small_bits: Vec<u64>,
+    edges: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    fn new(nodes: &Nodes) -> Option<Self> {
+        let caves = nodes
+            .iter()
+            .flat_map(|(cave, next_caves)| std::iter::once(cave).chain(next_caves))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .enumerate()
+            .map(|(i, cave)| (cave, i))
+            .collect::<BTreeMap<_, _>>();
+
+        if caves.keys().filter(|cave| cave.is_small()).count() > u64::BITS as usize {
+            return None;
+        }
+
+        let mut small = 0;
+        let small_bits = caves
+            .keys()
+            .map(|cave| {
+                if !cave.is_small() {
+                    return 0;
+                }
+
+                small += 1;
+                1 << (small - 1)
+            })
+            .collect();
+
+        let edges = caves
+            .keys()
+            .map(|&cave| {
+                nodes
+                    .get(cave)
+                    .into_iter()
+                    .flatten()
+                    .map(|next_cave| caves[next_cave])
+                    .collect()
+            })
+            .collect();
+
+        Some(Self {
+            start: caves[&Cave::Start],
+            end: caves[&Cave::End],
+            small_bits,
+            edges,
+        })
+    }
+
+    fn count_paths(&self, allow_twice: bool) -> usize {
+        self.count_paths_from(self.start, 0, allow_twice, &mut HashMap::new())
+    }
+
+    fn count_paths_from(
+        &self,
+        current: usize,
+        visited: u64,
+        allow_twice: bool,
+        memo: &mut HashMap<(usize, u64, bool), usize>,
+    ) -> usize {
+        if current == self.end {
+            return 1;
+        }
+
+        if let Some(&count) = memo.get(&(current, visited, allow_twice)) {
+            return count;
+        }
+
+        let count = self.edges[current]
+            .iter()
+            .map(|&next_cave| {
+                let bit = self.small_bits[next_cave];
+
+                if visited & bit == 0 {
+                    self.count_paths_from(next_cave, visited | bit, allow_twice, memo)
+                } else if allow_twice {
+                    self.count_paths_from(next_cave, visited, false, memo)
+                } else {
+                    0
+                }
+            })
+            .sum();
+
+        memo.insert((current, visited, allow_twice), count);
+        count
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PathFilter<'a> {
+    pub through: Option<&'a Cave>,
+    pub max_len: Option<usize>,
+}
+
+struct Paths<'a, P> {
+    nodes: &'a Nodes,
+    filter: PathFilter<'a>,
+    stack: Vec<(P, usize)>,
+    route: Vec<&'a Cave>,
+}
+
+impl<'a, P> Paths<'a, P>
 where
     P: Path<'a>,
 {
-    path.visit(current);
+    fn new(nodes: &'a Nodes, mut path: P, filter: PathFilter<'a>) -> Self {
+        path.visit(&Cave::Start);
+
+        Self {
+            nodes,
+            filter,
+            stack: vec![(path, 0)],
+            route: vec![&Cave::Start],
+        }
+    }
+}
+
+impl<'a, P> Iterator for Paths<'a, P>
+where
+    P: Path<'a>,
+{
+    type Item = Vec<&'a Cave>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((path, i)) = self.stack.last_mut() {
+            let current = self.route[self.route.len() - 1];
+            let next_cave = match self.nodes.get(current).and_then(|caves| caves.get(*i)) {
+                Some(next_cave) => next_cave,
+                None => {
+                    self.stack.pop();
+                    self.route.pop();
+                    continue;
+                }
+            };
+            *i += 1;
+
+            if !path.can_visit(next_cave)
+                || matches!(self.filter.max_len, Some(max_len) if self.route.len() >= max_len)
+            {
+                continue;
+            }
+
+            if let Cave::End = next_cave {
+                let mut route = self.route.clone();
+                route.push(next_cave);
 
-    if let Cave::End = current {
-        return 1;
+                if !path.accepts()
+                    || matches!(self.filter.through, Some(cave) if !route.contains(&cave))
+                {
+                    continue;
+                }
+
+                return Some(route);
+            }
+
+            let mut path = path.clone();
+            path.visit(next_cave);
+            self.stack.push((path, 0));
+            self.route.push(next_cave);
+        }
+
+        None
     }
+}
 
-    nodes[current]
+pub fn paths<'a, P>(
+    nodes: &'a Nodes,
+    path: P,
+    filter: PathFilter<'a>,
+) -> Result<impl Iterator<Item = Vec<&'a Cave>> + 'a, GraphError>
+where
+    P: Path<'a> + 'a,
+{
+    validate(nodes)?;
+    Ok(Paths::new(nodes, path, filter))
+}
+
+pub fn part1_paths<'a>(
+    nodes: &'a Nodes,
+    filter: PathFilter<'a>,
+) -> Result<impl Iterator<Item = Vec<&'a Cave>> + 'a, GraphError> {
+    paths(nodes, Part1Path::default(), filter)
+}
+
+pub fn part2_paths<'a>(
+    nodes: &'a Nodes,
+    filter: PathFilter<'a>,
+) -> Result<impl Iterator<Item = Vec<&'a Cave>> + 'a, GraphError> {
+    paths(nodes, Part2Path::default(), filter)
+}
+
+type Edge<'a> = (&'a Cave, &'a Cave);
+
+fn edge<'a>(a: &'a Cave, b: &'a Cave) -> Edge<'a> {
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+fn dot(nodes: &Nodes, weights: Option<BTreeMap<Edge, usize>>) -> String {
+    let caves = nodes
+        .iter()
+        .flat_map(|(cave, next_caves)| std::iter::once(cave).chain(next_caves))
+        .collect::<BTreeSet<_>>();
+    let edges = nodes
         .iter()
-        .filter(|next_cave| path.can_visit(next_cave))
-        .map(|next_cave| count_all_paths_from(nodes, path.clone(), next_cave))
-        .sum()
+        .flat_map(|(cave, next_caves)| {
+            next_caves
+                .iter()
+                .map(move |next_cave| edge(cave, next_cave))
+        })
+        .collect::<BTreeSet<_>>();
+
+    let mut out = String::from("graph caves {\n");
+
+    for cave in caves {
+        let attrs = match cave {
+            Cave::Start => "shape=doublecircle, style=filled, fillcolor=palegreen",
+            Cave::End => "shape=doublecircle, style=filled, fillcolor=lightcoral",
+            Cave::Small(_) => "shape=ellipse",
+            Cave::Big(_) => "shape=box",
+        };
+        out += &format!("    \"{}\" [{}];\n", cave, attrs);
+    }
+
+    let max_weight = weights
+        .iter()
+        .flat_map(|weights| weights.values())
+        .max()
+        .copied()
+        .unwrap_or(0)
+        .max(1);
+
+    for (a, b) in edges {
+        match &weights {
+            Some(weights) => {
+                let weight = weights.get(&(a, b)).copied().unwrap_or(0);
+                out += &format!(
+                    "    \"{}\" -- \"{}\" [label={}, penwidth={:.2}];\n",
+                    a,
+                    b,
+                    weight,
+                    1.0 + 4.0 * weight as f64 / max_weight as f64
+                );
+            }
+            None => out += &format!("    \"{}\" -- \"{}\";\n", a, b),
+        }
+    }
+
+    out += "}\n";
+    out
+}
+
+pub fn to_dot(nodes: &Nodes) -> String {
+    dot(nodes, None)
+}
+
+pub fn to_dot_weighted<'a, P>(nodes: &'a Nodes, path: P) -> Result<String, GraphError>
+where
+    P: Path<'a> + 'a,
+{
+    let weights =
+        paths(nodes, path, PathFilter::default())?.fold(BTreeMap::new(), |mut weights, route| {
+            let traversed = route
+                .iter()
+                .tuple_windows()
+                .map(|(a, b)| edge(a, b))
+                .collect::<BTreeSet<_>>();
+            for edge in traversed {
+                *weights.entry(edge).or_default() += 1;
+            }
+            weights
+        });
+
+    Ok(dot(nodes, Some(weights)))
 }
 
 #[aoc(day12, part1)]
-pub fn part1(nodes: &Nodes) -> usize {
-    count_all_paths_from(nodes, Part1Path::default(), &Cave::Start)
+pub fn part1(nodes: &Nodes) -> Result<usize, GraphError> {
+    validate(nodes)?;
+    Ok(match Graph::new(nodes) {
+        Some(graph) => graph.count_paths(false),
+        None => Paths::new(nodes, Part1Path::default(), PathFilter::default()).count(),
+    })
 }
 
 #[aoc(day12, part2)]
-pub fn part2(nodes: &Nodes) -> usize {
-    count_all_paths_from(nodes, Part2Path::default(), &Cave::Start)
+pub fn part2(nodes: &Nodes) -> Result<usize, GraphError> {
+    validate(nodes)?;
+    Ok(match Graph::new(nodes) {
+        Some(graph) => graph.count_paths(true),
+        None => Paths::new(nodes, Part2Path::default(), PathFilter::default()).count(),
+    })
 }
 
 #[cfg(test)]
@@ -181,47 +628,304 @@ start-RW";
 
     #[test]
     fn example1_1() {
-        assert_eq!(part1(&input_generator(EXAMPLE_1)), 10);
+        assert_eq!(part1(&input_generator(EXAMPLE_1)), Ok(10));
     }
 
     #[test]
     fn example1_2() {
-        assert_eq!(part1(&input_generator(EXAMPLE_2)), 19);
+        assert_eq!(part1(&input_generator(EXAMPLE_2)), Ok(19));
     }
 
     #[test]
     fn example1_3() {
-        assert_eq!(part1(&input_generator(EXAMPLE_3)), 226);
+        assert_eq!(part1(&input_generator(EXAMPLE_3)), Ok(226));
     }
 
     #[test]
     fn solution1() {
         assert_eq!(
             part1(&input_generator(include_str!("../input/2021/day12.txt"))),
-            5_457,
+            Ok(5_457),
         );
     }
 
     #[test]
     fn example2_1() {
-        assert_eq!(part2(&input_generator(EXAMPLE_1)), 36);
+        assert_eq!(part2(&input_generator(EXAMPLE_1)), Ok(36));
     }
 
     #[test]
     fn example2_2() {
-        assert_eq!(part2(&input_generator(EXAMPLE_2)), 103);
+        assert_eq!(part2(&input_generator(EXAMPLE_2)), Ok(103));
     }
 
     #[test]
     fn example2_3() {
-        assert_eq!(part2(&input_generator(EXAMPLE_3)), 3509);
+        assert_eq!(part2(&input_generator(EXAMPLE_3)), Ok(3509));
     }
 
     #[test]
     fn solution2() {
         assert_eq!(
             part2(&input_generator(include_str!("../input/2021/day12.txt"))),
-            128_506,
+            Ok(128_506),
+        );
+    }
+
+    fn names<'a>(paths: impl Iterator<Item = Vec<&'a Cave>>) -> Vec<String> {
+        paths.map(|path| path.into_iter().join(",")).collect()
+    }
+
+    #[test]
+    fn paths1() {
+        let nodes = input_generator(EXAMPLE_1);
+
+        assert_eq!(
+            names(part1_paths(&nodes, PathFilter::default()).unwrap()),
+            [
+                "start,A,c,A,b,A,end",
+                "start,A,c,A,b,end",
+                "start,A,c,A,end",
+                "start,A,b,A,c,A,end",
+                "start,A,b,A,end",
+                "start,A,b,end",
+                "start,A,end",
+                "start,b,A,c,A,end",
+                "start,b,A,end",
+                "start,b,end",
+            ]
+        );
+    }
+
+    #[test]
+    fn paths_match_counts() {
+        for example in [EXAMPLE_1, EXAMPLE_2, EXAMPLE_3] {
+            let nodes = input_generator(example);
+
+            assert_eq!(
+                part1_paths(&nodes, PathFilter::default()).unwrap().count(),
+                part1(&nodes).unwrap()
+            );
+            assert_eq!(
+                part2_paths(&nodes, PathFilter::default()).unwrap().count(),
+                part2(&nodes).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn paths_filter() {
+        let nodes = input_generator(EXAMPLE_1);
+        let c = Cave::Small("c".into());
+
+        assert_eq!(
+            names(
+                part1_paths(
+                    &nodes,
+                    PathFilter {
+                        through: Some(&c),
+                        ..PathFilter::default()
+                    }
+                )
+                .unwrap()
+            ),
+            [
+                "start,A,c,A,b,A,end",
+                "start,A,c,A,b,end",
+                "start,A,c,A,end",
+                "start,A,b,A,c,A,end",
+                "start,b,A,c,A,end",
+            ]
+        );
+
+        assert_eq!(
+            names(
+                part1_paths(
+                    &nodes,
+                    PathFilter {
+                        max_len: Some(4),
+                        ..PathFilter::default()
+                    }
+                )
+                .unwrap()
+            ),
+            [
+                "start,A,b,end",
+                "start,A,end",
+                "start,b,A,end",
+                "start,b,end"
+            ]
+        );
+    }
+
+    #[test]
+    fn generated_graph() {
+        let small = ["a", "b", "c", "d", "e", "f"];
+        let input = small
+            .iter()
+            .flat_map(|cave| {
+                [
+                    format!("start-{}", cave),
+                    format!("{}-end", cave),
+                    format!("{}-X", cave),
+                    format!("{}-Y", cave),
+                ]
+            })
+            .join("\n");
+        let nodes = input_generator(&input);
+
+        assert_eq!(
+            part1(&nodes).unwrap(),
+            part1_paths(&nodes, PathFilter::default()).unwrap().count()
+        );
+        assert_eq!(part1(&nodes).unwrap(), 37_986);
+    }
+
+    #[test]
+    fn many_small_caves() {
+        let name = |i: u8| format!("{}{}", (b'a' + i / 26) as char, (b'a' + i % 26) as char);
+        let input = (0..70)
+            .flat_map(|i| {
+                [
+                    format!("start-{}", name(i)),
+                    format!("{}-end", name(i)),
+                    format!("{}-{}", name(i), name(i + 1)),
+                ]
+            })
+            .take(3 * 70 - 1)
+            .join("\n");
+        let nodes = input_generator(&input);
+
+        assert_eq!(part1(&nodes), Ok(70 * 70));
+    }
+
+    #[test]
+    fn revisit_presets() {
+        for example in [EXAMPLE_1, EXAMPLE_2, EXAMPLE_3] {
+            let nodes = input_generator(example);
+
+            assert_eq!(
+                paths(&nodes, RevisitPolicy::part1(), PathFilter::default())
+                    .unwrap()
+                    .count(),
+                part1(&nodes).unwrap()
+            );
+            assert_eq!(
+                paths(&nodes, RevisitPolicy::part2(), PathFilter::default())
+                    .unwrap()
+                    .count(),
+                part2(&nodes).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn revisit_policy() {
+        let nodes = input_generator(EXAMPLE_1);
+        let c = [Cave::Small("c".into())];
+
+        let count = |policy| {
+            paths(&nodes, policy, PathFilter::default())
+                .unwrap()
+                .count()
+        };
+
+        assert_eq!(count(RevisitPolicy::part1().forbid(&c)), 5);
+        assert_eq!(count(RevisitPolicy::part1().require(&c)), 5);
+        assert_eq!(count(RevisitPolicy::new(2, 2)), 54);
+        assert_eq!(count(RevisitPolicy::new(2, 2).forbid(&c)), 13);
+        assert_eq!(count(RevisitPolicy::new(1, 3)), 83);
+    }
+
+    #[test]
+    fn validation() {
+        let big = |name: &str| Cave::Big(name.into());
+        let small = |name: &str| Cave::Small(name.into());
+
+        assert_eq!(
+            part1(&input_generator("start-A\nA-B\nB-end")),
+            Err(GraphError::BigCavesAdjacent(big("A"), big("B")))
+        );
+        assert_eq!(
+            part2(&input_generator("a-end\na-b")),
+            Err(GraphError::MissingStart)
+        );
+        assert_eq!(
+            part1(&input_generator("start-a\nb-end")),
+            Err(GraphError::UnreachableEnd)
+        );
+        assert_eq!(
+            part1(&input_generator("start-a\na-end\nb-c")),
+            Err(GraphError::IsolatedCave(small("b")))
+        );
+        assert_eq!(
+            validate(&input_generator("start-a\na-end\nb-end")),
+            Err(GraphError::IsolatedCave(small("b")))
+        );
+        assert_eq!(
+            validate(&input_generator("start-a\na-end\nend-b")),
+            Err(GraphError::IsolatedCave(small("b")))
+        );
+        assert_eq!(validate(&input_generator(EXAMPLE_3)), Ok(()));
+        assert_eq!(
+            part1_paths(
+                &input_generator("start-A\nA-B\nB-end"),
+                PathFilter::default()
+            )
+            .err(),
+            Some(GraphError::BigCavesAdjacent(big("A"), big("B")))
+        );
+        assert_eq!(
+            GraphError::BigCavesAdjacent(big("A"), big("B")).to_string(),
+            "big caves A and B are adjacent, paths would be infinite"
+        );
+    }
+
+    #[test]
+    fn dot_() {
+        let nodes = input_generator(EXAMPLE_1);
+
+        assert_eq!(
+            to_dot(&nodes),
+            r#"graph caves {
+    "start" [shape=doublecircle, style=filled, fillcolor=palegreen];
+    "end" [shape=doublecircle, style=filled, fillcolor=lightcoral];
+    "b" [shape=ellipse];
+    "c" [shape=ellipse];
+    "d" [shape=ellipse];
+    "A" [shape=box];
+    "start" -- "b";
+    "start" -- "A";
+    "end" -- "b";
+    "end" -- "A";
+    "b" -- "d";
+    "b" -- "A";
+    "c" -- "A";
+}
+"#
+        );
+    }
+
+    #[test]
+    fn dot_weighted() {
+        let nodes = input_generator(EXAMPLE_1);
+
+        let part1 = to_dot_weighted(&nodes, RevisitPolicy::part1()).unwrap();
+        assert!(part1.contains(r#""start" -- "A" [label=7, penwidth=5.00];"#));
+        assert!(part1.contains(r#""b" -- "d" [label=0, penwidth=1.00];"#));
+
+        let part2 = to_dot_weighted(&nodes, RevisitPolicy::part2()).unwrap();
+        assert!(part2.contains(r#""b" -- "d" [label=8, penwidth="#));
+
+        assert_eq!(
+            to_dot_weighted(
+                &input_generator("start-A\nA-B\nB-end"),
+                RevisitPolicy::part1()
+            ),
+            Err(GraphError::BigCavesAdjacent(
+                Cave::Big("A".into()),
+                Cave::Big("B".into())
+            ))
         );
     }
 }
@@ -1,13 +1,26 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
-use ndarray::prelude::*;
+use ndarray::{prelude::*, Slice};
+use std::{cmp::Ordering, collections::HashSet, error::Error, fmt};
+
+type Point = (usize, usize);
 
 #[derive(Debug)]
 pub struct Manual {
-    paper: Array2<bool>,
+    dots: Vec<Point>,
     instructions: Vec<Fold>,
 }
 
+impl Manual {
+    pub fn dense(&self) -> Array2<bool> {
+        Paper::from_dots(&self.dots)
+    }
+
+    pub fn sparse(&self) -> SparsePaper {
+        Paper::from_dots(&self.dots)
+    }
+}
+
 #[derive(Debug)]
 pub enum Fold {
     X(usize),
@@ -16,28 +29,15 @@ pub enum Fold {
 
 #[aoc_generator(day13)]
 pub fn input_generator(input: &str) -> Manual {
-    let (paper, instructions) = input.trim().split("\n\n").collect_tuple().unwrap();
-
-    let paper = {
-        let cells = paper
-            .lines()
-            .map(|line| -> (usize, usize) {
-                let (x, y) = line.split(',').collect_tuple().unwrap();
-                (x.parse().unwrap(), y.parse().unwrap())
-            })
-            .collect_vec();
-
-        let rows = cells.iter().map(|(x, _)| x).max().unwrap();
-        let cols = cells.iter().map(|(_, y)| y).max().unwrap();
+    let (dots, instructions) = input.trim().split("\n\n").collect_tuple().unwrap();
 
-        let mut paper = Array2::from_elem((*cols + 1, *rows + 1), false);
-
-        for (x, y) in cells {
-            paper[(y, x)] = true;
-        }
-
-        paper
-    };
+    let dots = dots
+        .lines()
+        .map(|line| {
+            let (x, y) = line.split(',').collect_tuple().unwrap();
+            (x.parse().unwrap(), y.parse().unwrap())
+        })
+        .collect_vec();
 
     let instructions = instructions
         .lines()
@@ -52,51 +52,242 @@ pub fn input_generator(input: &str) -> Manual {
         })
         .collect_vec();
 
-    Manual {
-        paper,
-        instructions,
-    }
+    Manual { dots, instructions }
+}
+
+pub trait Paper: Sized {
+    fn from_dots(dots: &[Point]) -> Self;
+    fn fold(self, fold: &Fold) -> Self;
+    fn count_dots(&self) -> usize;
+    fn to_dense(&self) -> Array2<bool>;
 }
 
-fn fold<'a>(paper: Array2<bool>, instrs: impl Iterator<Item = &'a Fold>) -> Array2<bool> {
-    instrs.fold(paper, |paper, fold| {
+impl Paper for Array2<bool> {
+    fn from_dots(dots: &[Point]) -> Self {
+        let cols = dots.iter().map(|&(x, _)| x + 1).max().unwrap_or(0);
+        let rows = dots.iter().map(|&(_, y)| y + 1).max().unwrap_or(0);
+
+        let mut paper = Array2::from_elem((rows, cols), false);
+
+        for &(x, y) in dots {
+            paper[(y, x)] = true;
+        }
+
+        paper
+    }
+
+    fn fold(self, fold: &Fold) -> Self {
         let (axis, idx) = match fold {
             Fold::Y(idx) => (Axis(0), *idx),
             Fold::X(idx) => (Axis(1), *idx),
         };
 
-        let (a, almost_b) = paper.view().split_at(axis, idx);
-        let (_, mut b) = almost_b.split_at(axis, 1);
-        b.invert_axis(axis);
+        let len = self.len_of(axis);
+        let folded_len = folded_len(idx, len);
+
+        let mut dim = self.raw_dim();
+        dim[axis.index()] = folded_len;
+        let mut folded = Array2::from_elem(dim, false);
+
+        let a_len = idx.min(len);
+        let offset = folded_len - idx;
+        let mut a = folded.slice_axis_mut(axis, Slice::from(offset..offset + a_len));
+        a |= &self.slice_axis(axis, Slice::from(..a_len));
+
+        if len > idx + 1 {
+            let mut b = self.slice_axis(axis, Slice::from(idx + 1..));
+            b.invert_axis(axis);
+            let b_len = b.len_of(axis);
+            let mut overlap = folded.slice_axis_mut(axis, Slice::from(folded_len - b_len..));
+            overlap |= &b;
+        }
+
+        folded
+    }
+
+    fn count_dots(&self) -> usize {
+        self.iter().filter(|&&c| c).count()
+    }
+
+    fn to_dense(&self) -> Array2<bool> {
+        self.clone()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparsePaper {
+    pub dots: HashSet<Point>,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Paper for SparsePaper {
+    fn from_dots(dots: &[Point]) -> Self {
+        Self {
+            dots: dots.iter().copied().collect(),
+            width: dots.iter().map(|&(x, _)| x + 1).max().unwrap_or(0),
+            height: dots.iter().map(|&(_, y)| y + 1).max().unwrap_or(0),
+        }
+    }
+
+    fn fold(self, fold: &Fold) -> Self {
+        let (idx, len) = match fold {
+            Fold::X(idx) => (*idx, self.width),
+            Fold::Y(idx) => (*idx, self.height),
+        };
+        let folded_len = folded_len(idx, len);
+
+        let reflect = |c: usize| match c.cmp(&idx) {
+            Ordering::Less => Some(c + folded_len - idx),
+            Ordering::Equal => None,
+            Ordering::Greater => Some(folded_len + idx - c),
+        };
+
+        let dots = self
+            .dots
+            .into_iter()
+            .filter_map(|(x, y)| match fold {
+                Fold::X(_) => Some((reflect(x)?, y)),
+                Fold::Y(_) => Some((x, reflect(y)?)),
+            })
+            .collect();
+
+        match fold {
+            Fold::X(_) => Self {
+                dots,
+                width: folded_len,
+                ..self
+            },
+            Fold::Y(_) => Self {
+                dots,
+                height: folded_len,
+                ..self
+            },
+        }
+    }
+
+    fn count_dots(&self) -> usize {
+        self.dots.len()
+    }
+
+    fn to_dense(&self) -> Array2<bool> {
+        let mut paper = Array2::from_elem((self.height, self.width), false);
 
-        a.to_owned() | b
+        for &(x, y) in &self.dots {
+            paper[(y, x)] = true;
+        }
+
+        paper
+    }
+}
+
+fn folded_len(idx: usize, len: usize) -> usize {
+    idx.max(len.saturating_sub(idx + 1))
+}
+
+fn fold<'a, P: Paper>(paper: P, instrs: impl Iterator<Item = &'a Fold>) -> P {
+    instrs.fold(paper, P::fold)
+}
+
+pub fn folds<'a, P>(
+    paper: P,
+    instrs: impl IntoIterator<Item = &'a Fold> + 'a,
+) -> impl Iterator<Item = P> + 'a
+where
+    P: Paper + Clone + 'a,
+{
+    instrs.into_iter().scan(paper, |paper, fold| {
+        *paper = paper.clone().fold(fold);
+        Some(paper.clone())
     })
 }
 
-fn count_dots(paper: &Array2<bool>) -> usize {
-    paper.iter().filter(|&&c| c).count()
+pub fn render(paper: &Array2<bool>) -> String {
+    paper
+        .rows()
+        .into_iter()
+        .map(|row| {
+            row.iter()
+                .map(|&dot| if dot { '#' } else { '.' })
+                .collect::<String>()
+                + "\n"
+        })
+        .collect()
+}
+
+const GLYPH_WIDTH: usize = 4;
+const GLYPH_HEIGHT: usize = 6;
+
+static FONT: &[(char, &str)] = &[
+    ('A', ".##.#..##..######..##..#"),
+    ('B', "###.#..####.#..##..####."),
+    ('C', ".##.#..##...#...#..#.##."),
+    ('E', "#####...###.#...#...####"),
+    ('F', "#####...###.#...#...#..."),
+    ('G', ".##.#..##...#.###..#.###"),
+    ('H', "#..##..######..##..##..#"),
+    ('I', ".###..#...#...#...#..###"),
+    ('J', "..##...#...#...##..#.##."),
+    ('K', "#..##.#.##..#.#.#.#.#..#"),
+    ('L', "#...#...#...#...#...####"),
+    ('O', ".##.#..##..##..##..#.##."),
+    ('P', "###.#..##..####.#...#..."),
+    ('R', "###.#..##..####.#.#.#..#"),
+    ('S', ".####...#....##....####."),
+    ('U', "#..##..##..##..##..#.##."),
+    ('Y', "#...#....#.#..#...#...#."),
+    ('Z', "####...#..#..#..#...####"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownGlyph {
+    pub index: usize,
+    pub bitmap: String,
+}
+
+impl fmt::Display for UnknownGlyph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "unknown glyph at position {}:", self.index)?;
+        for row in self.bitmap.as_bytes().chunks(GLYPH_WIDTH) {
+            writeln!(f, "{}", String::from_utf8_lossy(row))?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for UnknownGlyph {}
+
+pub fn recognise(paper: &Array2<bool>) -> Result<String, UnknownGlyph> {
+    let letters = (paper.ncols() + GLYPH_WIDTH) / (GLYPH_WIDTH + 1);
+
+    (0..letters)
+        .map(|index| {
+            let bitmap = (0..GLYPH_HEIGHT)
+                .cartesian_product(0..GLYPH_WIDTH)
+                .map(
+                    |(y, x)| match paper.get((y, index * (GLYPH_WIDTH + 1) + x)) {
+                        Some(true) => '#',
+                        _ => '.',
+                    },
+                )
+                .collect::<String>();
+
+            FONT.iter()
+                .find(|(_, glyph)| *glyph == bitmap)
+                .map(|&(letter, _)| letter)
+                .ok_or(UnknownGlyph { index, bitmap })
+        })
+        .collect()
 }
 
 #[aoc(day13, part1)]
-pub fn part1(
-    Manual {
-        paper,
-        instructions,
-    }: &Manual,
-) -> usize {
-    let folded = fold(paper.clone(), instructions.iter().take(1));
-    count_dots(&folded)
+pub fn part1(manual: &Manual) -> usize {
+    fold(manual.sparse(), manual.instructions.iter().take(1)).count_dots()
 }
 
 #[aoc(day13, part2)]
-pub fn part2(
-    Manual {
-        paper,
-        instructions,
-    }: &Manual,
-) -> usize {
-    let folded = fold(paper.clone(), instructions.iter());
-    count_dots(&folded)
+pub fn part2(manual: &Manual) -> Result<String, UnknownGlyph> {
+    recognise(&fold(manual.sparse(), manual.instructions.iter()).to_dense())
 }
 
 #[cfg(test)]
@@ -142,7 +333,133 @@ fold along x=5";
     fn solution2() {
         assert_eq!(
             part2(&input_generator(include_str!("../input/2021/day13.txt"))),
-            95,
+            Ok("PZFJHRFZ".into()),
+        );
+    }
+
+    #[test]
+    fn recognise_() {
+        let manual = input_generator(EXAMPLE);
+        let folded = fold(manual.dense(), manual.instructions.iter());
+
+        assert_eq!(
+            recognise(&folded),
+            Err(UnknownGlyph {
+                index: 0,
+                bitmap: "#####...#...#...####....".into()
+            })
+        );
+    }
+
+    #[test]
+    fn folds_() {
+        let manual = input_generator(EXAMPLE);
+
+        assert_eq!(
+            folds(manual.dense(), &manual.instructions)
+                .map(|paper| render(&paper))
+                .collect_vec(),
+            [
+                "#.##..#..#.
+#...#......
+......#...#
+#...#......
+.#.#..#.###
+...........
+...........
+",
+                "#####
+#...#
+#...#
+#...#
+#####
+.....
+.....
+"
+            ]
         );
     }
+
+    #[test]
+    fn sparse_matches_dense() {
+        for input in [EXAMPLE, include_str!("../input/2021/day13.txt")] {
+            let manual = input_generator(input);
+
+            for (dense, sparse) in folds(manual.dense(), &manual.instructions)
+                .zip(folds(manual.sparse(), &manual.instructions))
+            {
+                assert_eq!(dense, sparse.to_dense());
+            }
+        }
+    }
+
+    #[test]
+    fn sparse_huge_coordinates() {
+        let manual = input_generator(
+            "0,0
+4000000,2000002
+4000000,2000004
+
+fold along y=2000003
+fold along x=2000000",
+        );
+
+        assert_eq!(
+            fold(manual.sparse(), manual.instructions.iter()),
+            SparsePaper {
+                dots: HashSet::from([(0, 0), (0, 2000002)]),
+                width: 2000000,
+                height: 2000003,
+            }
+        );
+    }
+
+    fn fold_both(input: &str) -> (String, String) {
+        let manual = input_generator(input);
+        let dense = fold(manual.dense(), manual.instructions.iter());
+        let sparse = fold(manual.sparse(), manual.instructions.iter());
+        assert_eq!(dense, sparse.to_dense());
+
+        (render(&dense), render(&sparse.to_dense()))
+    }
+
+    #[test]
+    fn asymmetric_fold_y() {
+        let input = "0,0
+1,2
+0,4
+1,4
+
+fold along";
+
+        let (dense, sparse) = fold_both(&format!("{} y=1", input));
+        assert_eq!(dense, "##\n..\n##\n");
+        assert_eq!(dense, sparse);
+
+        let (dense, sparse) = fold_both(&format!("{} y=3", input));
+        assert_eq!(dense, "#.\n..\n##\n");
+        assert_eq!(dense, sparse);
+
+        let (dense, sparse) = fold_both(&format!("{} y=5", input));
+        assert_eq!(dense, "#.\n..\n.#\n..\n##\n");
+        assert_eq!(dense, sparse);
+    }
+
+    #[test]
+    fn asymmetric_fold_x() {
+        let input = "0,0
+2,1
+4,0
+4,1
+
+fold along";
+
+        let (dense, sparse) = fold_both(&format!("{} x=1", input));
+        assert_eq!(dense, "#.#\n#.#\n");
+        assert_eq!(dense, sparse);
+
+        let (dense, sparse) = fold_both(&format!("{} x=3", input));
+        assert_eq!(dense, "#.#\n..#\n");
+        assert_eq!(dense, sparse);
+    }
 }
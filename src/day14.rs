@@ -3,125 +3,458 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
 use ndarray::prelude::*;
-use std::{ops::AddAssign, str::FromStr};
+use num_bigint::BigUint;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+};
 
-const N: usize = 26;
-
-#[derive(Debug, Clone, Copy)]
-pub struct Element(char);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Element(usize);
 
 impl Element {
     fn idx(self) -> usize {
-        debug_assert!(self.0 >= 'A' && self.0 <= 'Z');
-        self.0 as usize - 'A' as usize
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Alphabet {
+    symbols: Vec<String>,
+    elements: HashMap<String, Element>,
+}
+
+impl Alphabet {
+    fn intern(&mut self, symbol: &str) -> Element {
+        if let Some(&element) = self.elements.get(symbol) {
+            return element;
+        }
+
+        let element = Element(self.symbols.len());
+        self.symbols.push(symbol.into());
+        self.elements.insert(symbol.into(), element);
+        element
+    }
+
+    pub fn symbol(&self, element: Element) -> &str {
+        &self.symbols[element.idx()]
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
     }
 }
 
 pub type Rule = (Element, Element, Element);
 pub type Rules = Vec<Rule>;
 
+fn insertions(rules: &[Rule]) -> HashMap<(Element, Element), Element> {
+    rules.iter().map(|&(a, b, c)| ((a, b), c)).collect()
+}
+
 #[derive(Debug, Clone)]
 pub struct Template {
-    singles: Array1<isize>,
-    pairs: Array2<isize>,
+    singles: Array1<u64>,
+    pairs: Array2<u64>,
+}
+
+fn add(slot: &mut u64, count: u64) -> Option<()> {
+    *slot = slot.checked_add(count)?;
+    Some(())
 }
 
-impl Default for Template {
-    fn default() -> Self {
+impl Template {
+    fn zeros(n: usize) -> Self {
         Self {
-            singles: Array1::from_elem(N, 0),
-            pairs: Array2::from_elem((N, N), 0),
+            singles: Array1::from_elem(n, 0),
+            pairs: Array2::from_elem((n, n), 0),
         }
     }
-}
 
-impl FromStr for Template {
-    type Err = ();
+    fn new(elements: &[Element], n: usize) -> Self {
+        let mut template = Self::zeros(n);
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut singles = Array1::from_elem(N, 0);
-        for c in s.chars().map(Element) {
-            singles[c.idx()] += 1;
+        for e in elements {
+            template.singles[e.idx()] += 1;
         }
 
-        let mut pairs = Array2::from_elem((N, N), 0);
-        for (a, b) in s.chars().map(Element).tuple_windows() {
-            pairs[(a.idx(), b.idx())] += 1;
+        for (a, b) in elements.iter().tuple_windows() {
+            template.pairs[(a.idx(), b.idx())] += 1;
         }
 
-        Ok(Self { singles, pairs })
+        template
     }
+
+    fn step(&self, insertions: &HashMap<(Element, Element), Element>) -> Option<Self> {
+        let mut next = Self {
+            singles: self.singles.clone(),
+            pairs: Array2::zeros(self.pairs.dim()),
+        };
+
+        for ((a, b), &count) in self.pairs.indexed_iter() {
+            match insertions.get(&(Element(a), Element(b))) {
+                Some(c) => {
+                    add(&mut next.pairs[(a, c.idx())], count)?;
+                    add(&mut next.pairs[(c.idx(), b)], count)?;
+                    add(&mut next.singles[c.idx()], count)?;
+                }
+                None => add(&mut next.pairs[(a, b)], count)?,
+            }
+        }
+
+        Some(next)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Formula {
+    pub alphabet: Alphabet,
+    pub polymer: Vec<Element>,
+    pub template: Template,
+    pub rules: Rules,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingRule {
+    pub pair: (String, String),
+    pub step: usize,
 }
 
-impl AddAssign<&Template> for Template {
-    fn add_assign(&mut self, rhs: &Template) {
-        self.singles += &rhs.singles;
-        self.pairs += &rhs.pairs;
+impl fmt::Display for MissingRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "no insertion rule for pair {} {} at step {}",
+            self.pair.0, self.pair.1, self.step
+        )
     }
 }
 
-impl Template {
-    fn step(&self, rules: &[Rule]) -> Self {
-        let mut next = Self::default();
+impl Error for MissingRule {}
 
-        for (a, b, c) in rules {
-            let count = self.pairs[(a.idx(), b.idx())];
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HistogramError {
+    MissingRule(MissingRule),
+    Overflow { step: usize },
+}
 
-            next.pairs[(a.idx(), b.idx())] -= count;
-            next.pairs[(a.idx(), c.idx())] += count;
-            next.pairs[(c.idx(), b.idx())] += count;
-            next.singles[c.idx()] += count;
+impl fmt::Display for HistogramError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistogramError::MissingRule(missing) => write!(f, "{}", missing),
+            HistogramError::Overflow { step } => {
+                write!(f, "element counts overflow u64 at step {}", step)
+            }
         }
+    }
+}
+
+impl Error for HistogramError {}
+
+impl From<MissingRule> for HistogramError {
+    fn from(missing: MissingRule) -> Self {
+        HistogramError::MissingRule(missing)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    pub counts: Vec<(String, u64)>,
+    pub len: u64,
+}
+
+impl Histogram {
+    pub fn diff(&self) -> u64 {
+        match (self.counts.first(), self.counts.last()) {
+            (Some((_, max)), Some((_, min))) => max - min,
+            _ => 0,
+        }
+    }
+}
+
+impl Formula {
+    fn missing_rule(&self, a: Element, b: Element, step: usize) -> MissingRule {
+        MissingRule {
+            pair: (
+                self.alphabet.symbol(a).into(),
+                self.alphabet.symbol(b).into(),
+            ),
+            step,
+        }
+    }
 
-        next += self;
-        next
+    fn insertions(&self) -> HashMap<(Element, Element), Element> {
+        insertions(&self.rules)
     }
 
-    fn diff(&self) -> usize {
-        let mut singles = self.singles.as_slice().unwrap().to_vec();
-        let partition_idx = itertools::partition(&mut singles, |&c| c == 0);
-        let (min, max) = singles[partition_idx..]
+    pub fn histogram(&self, steps: usize) -> Result<Histogram, HistogramError> {
+        let insertions = self.insertions();
+        let mut template = self.template.clone();
+
+        for step in 1..=steps {
+            if let Some(((a, b), _)) = template.pairs.indexed_iter().find(|&((a, b), &count)| {
+                count > 0 && !insertions.contains_key(&(Element(a), Element(b)))
+            }) {
+                return Err(self.missing_rule(Element(a), Element(b), step).into());
+            }
+
+            template = template
+                .step(&insertions)
+                .ok_or(HistogramError::Overflow { step })?;
+        }
+
+        let counts = template
+            .singles
+            .indexed_iter()
+            .filter(|(_, &count)| count > 0)
+            .map(|(i, &count)| (self.alphabet.symbol(Element(i)).to_owned(), count))
+            .sorted_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)))
+            .collect_vec();
+
+        let len = counts
             .iter()
-            .minmax()
-            .into_option()
-            .unwrap();
-        (max - min) as usize
+            .try_fold(0_u64, |len, (_, count)| len.checked_add(*count))
+            .ok_or(HistogramError::Overflow { step: steps })?;
+
+        Ok(Histogram { len, counts })
+    }
+
+    pub fn expand(&self, steps: usize) -> Result<String, MissingRule> {
+        let insertions = self.insertions();
+        let mut polymer = self.polymer.clone();
+
+        for step in 1..=steps {
+            let mut next = polymer.iter().copied().take(1).collect_vec();
+
+            for (&a, &b) in polymer.iter().tuple_windows() {
+                let c = insertions
+                    .get(&(a, b))
+                    .ok_or_else(|| self.missing_rule(a, b, step))?;
+                next.extend([*c, b]);
+            }
+
+            polymer = next;
+        }
+
+        let separator = if self.alphabet.symbols.iter().all(|s| s.chars().count() == 1) {
+            ""
+        } else {
+            " "
+        };
+
+        Ok(polymer
+            .into_iter()
+            .map(|element| self.alphabet.symbol(element))
+            .join(separator))
+    }
+}
+
+fn symbols(s: &str) -> Vec<&str> {
+    if s.contains(char::is_whitespace) {
+        s.split_whitespace().collect()
+    } else {
+        s.char_indices()
+            .map(|(i, c)| &s[i..i + c.len_utf8()])
+            .collect()
     }
 }
 
 #[aoc_generator(day14)]
-pub fn input_generator(input: &str) -> (Template, Rules) {
+pub fn input_generator(input: &str) -> Formula {
     let (template, rules) = input.trim().split("\n\n").collect_tuple().unwrap();
 
-    let template = template.parse().unwrap();
+    let mut alphabet = Alphabet::default();
+
+    let template = symbols(template.trim())
+        .into_iter()
+        .map(|symbol| alphabet.intern(symbol))
+        .collect_vec();
     let rules = rules
         .lines()
         .map(|line| {
-            let (pairs, elem) = line.split(" -> ").collect_tuple().unwrap();
-            let (a, b) = pairs.chars().map(Element).collect_tuple().unwrap();
+            let (pair, elem) = line.split(" -> ").collect_tuple().unwrap();
+            let (a, b) = symbols(pair.trim()).into_iter().collect_tuple().unwrap();
 
-            (a, b, elem.chars().map(Element).next().unwrap())
+            (
+                alphabet.intern(a),
+                alphabet.intern(b),
+                alphabet.intern(elem.trim()),
+            )
         })
         .collect();
 
-    (template, rules)
+    Formula {
+        template: Template::new(&template, alphabet.len()),
+        polymer: template,
+        alphabet,
+        rules,
+    }
+}
+
+pub trait Count: Clone {
+    fn from_u64(n: u64) -> Self;
+    fn add(&self, rhs: &Self) -> Self;
+    fn mul(&self, rhs: &Self) -> Self;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Modular<const M: u64>(pub u64);
+
+impl<const M: u64> Count for Modular<M> {
+    fn from_u64(n: u64) -> Self {
+        Self(n % M)
+    }
+
+    fn add(&self, rhs: &Self) -> Self {
+        Self(((self.0 as u128 + rhs.0 as u128) % M as u128) as u64)
+    }
+
+    fn mul(&self, rhs: &Self) -> Self {
+        Self(((self.0 as u128 * rhs.0 as u128) % M as u128) as u64)
+    }
+}
+
+impl Count for BigUint {
+    fn from_u64(n: u64) -> Self {
+        n.into()
+    }
+
+    fn add(&self, rhs: &Self) -> Self {
+        self + rhs
+    }
+
+    fn mul(&self, rhs: &Self) -> Self {
+        self * rhs
+    }
+}
+
+fn mat_mul<T: Count>(a: &Array2<T>, b: &Array2<T>) -> Array2<T> {
+    let zero = T::from_u64(0);
+
+    Array2::from_shape_fn((a.nrows(), b.ncols()), |(i, j)| {
+        a.row(i)
+            .iter()
+            .zip(b.column(j))
+            .fold(zero.clone(), |acc, (x, y)| acc.add(&x.mul(y)))
+    })
+}
+
+fn mat_pow<T: Count>(mut base: Array2<T>, mut exp: u64) -> Array2<T> {
+    let n = base.nrows();
+    let mut acc = Array2::from_shape_fn((n, n), |(i, j)| T::from_u64((i == j) as u64));
+
+    while exp > 0 {
+        if exp & 1 == 1 {
+            acc = mat_mul(&acc, &base);
+        }
+        exp >>= 1;
+        if exp > 0 {
+            base = mat_mul(&base, &base);
+        }
+    }
+
+    acc
+}
+
+impl Template {
+    // State vector is every pair count followed by every single count, so
+    // that a single matrix both rewrites pairs and accumulates insertions.
+    fn transition<T: Count>(&self, rules: &[Rule]) -> Array2<T> {
+        let n = self.singles.len();
+        let pair = |a: Element, b: Element| a.idx() * n + b.idx();
+
+        let mut transition = Array2::from_elem((n * n + n, n * n + n), 0_u64);
+
+        for i in 0..n * n + n {
+            transition[(i, i)] = 1;
+        }
+
+        for ((a, b), c) in insertions(rules) {
+            let ab = pair(a, b);
+            transition[(ab, ab)] -= 1;
+            transition[(pair(a, c), ab)] += 1;
+            transition[(pair(c, b), ab)] += 1;
+            transition[(n * n + c.idx(), ab)] += 1;
+        }
+
+        transition.mapv(T::from_u64)
+    }
+
+    fn counts_at<T: Count>(&self, rules: &[Rule], steps: u64) -> Vec<T> {
+        let n = self.singles.len();
+
+        let state = self
+            .pairs
+            .iter()
+            .chain(&self.singles)
+            .map(|&count| T::from_u64(count))
+            .collect::<Array1<_>>()
+            .insert_axis(Axis(1));
+
+        let state = mat_mul(&mat_pow(self.transition(rules), steps), &state);
+
+        state.iter().skip(n * n).cloned().collect()
+    }
+}
+
+impl Formula {
+    fn check_rules(&self, steps: u64) -> Result<(), MissingRule> {
+        let insertions = self.insertions();
+        let mut seen = HashSet::new();
+        let mut pairs = self.polymer.iter().copied().tuple_windows().collect_vec();
+
+        for step in (1..).take_while(|&step| step <= steps) {
+            pairs.retain(|&pair| seen.insert(pair));
+            if pairs.is_empty() {
+                break;
+            }
+            pairs.sort_by_key(|(a, b)| (a.idx(), b.idx()));
+
+            let mut next = Vec::new();
+            for &(a, b) in &pairs {
+                let &c = insertions
+                    .get(&(a, b))
+                    .ok_or_else(|| self.missing_rule(a, b, step as usize))?;
+                next.extend([(a, c), (c, b)]);
+            }
+            pairs = next;
+        }
+
+        Ok(())
+    }
+
+    pub fn histogram_at<T: Count>(&self, steps: u64) -> Result<Vec<(String, T)>, MissingRule> {
+        self.check_rules(steps)?;
+
+        Ok(self
+            .template
+            .counts_at(&self.rules, steps)
+            .into_iter()
+            .enumerate()
+            .map(|(i, count)| (self.alphabet.symbol(Element(i)).to_owned(), count))
+            .collect())
+    }
 }
 
 #[aoc(day14, part1)]
-pub fn part1((template, rules): &(Template, Rules)) -> usize {
-    let template = (0..10).fold(template.clone(), |template, _| template.step(rules));
-    template.diff()
+pub fn part1(formula: &Formula) -> Result<u64, HistogramError> {
+    Ok(formula.histogram(10)?.diff())
 }
 
 #[aoc(day14, part2)]
-pub fn part2((template, rules): &(Template, Rules)) -> usize {
-    let template = (0..40).fold(template.clone(), |template, _| template.step(rules));
-    template.diff()
+pub fn part2(formula: &Formula) -> Result<u64, HistogramError> {
+    Ok(formula.histogram(40)?.diff())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::Lcg;
 
     static EXAMPLE: &str = "NNCB
 
@@ -144,14 +477,14 @@ CN -> C";
 
     #[test]
     fn example1() {
-        assert_eq!(part1(&input_generator(EXAMPLE)), 1588);
+        assert_eq!(part1(&input_generator(EXAMPLE)), Ok(1588));
     }
 
     #[test]
     fn solution1() {
         assert_eq!(
             part1(&input_generator(include_str!("../input/2021/day14.txt"))),
-            3_587,
+            Ok(3_587),
         );
     }
 
@@ -159,7 +492,196 @@ CN -> C";
     fn solution2() {
         assert_eq!(
             part2(&input_generator(include_str!("../input/2021/day14.txt"))),
-            3_906_445_077_999,
+            Ok(3_906_445_077_999),
         );
     }
+
+    #[test]
+    fn alphabets() {
+        let translate = |mapping: &[(char, &str)]| {
+            EXAMPLE
+                .chars()
+                .map(|c| {
+                    mapping
+                        .iter()
+                        .find(|&&(from, _)| from == c)
+                        .map_or(c.to_string(), |&(_, to)| to.to_string())
+                })
+                .collect::<String>()
+        };
+
+        let lowercase_and_digits = translate(&[('N', "n"), ('C', "1"), ('B', "b"), ('H', "9")]);
+        assert_eq!(part1(&input_generator(&lowercase_and_digits)), Ok(1588));
+
+        let multi_char = translate(&[('N', "Na "), ('C', "Ca "), ('B', "B "), ('H', "He ")]);
+        let formula = input_generator(&multi_char);
+        assert_eq!(formula.alphabet.len(), 4);
+        assert_eq!(formula.alphabet.symbol(Element(1)), "Ca");
+        assert_eq!(part1(&formula), Ok(1588));
+    }
+
+    fn counts<T>(histogram: Vec<(String, T)>) -> Vec<T> {
+        histogram.into_iter().map(|(_, count)| count).collect()
+    }
+
+    fn diff(histogram: &[(String, BigUint)]) -> BigUint {
+        let (min, max) = histogram
+            .iter()
+            .map(|(_, count)| count)
+            .filter(|&count| count != &BigUint::from(0_u8))
+            .minmax()
+            .into_option()
+            .unwrap();
+        max - min
+    }
+
+    #[test]
+    fn histogram_big() {
+        let formula = input_generator(EXAMPLE);
+        let histogram = formula.histogram_at::<BigUint>(10).unwrap();
+
+        let count = |symbol| {
+            histogram
+                .iter()
+                .find(|(s, _)| s == symbol)
+                .map(|(_, count)| count.clone())
+                .unwrap()
+        };
+        assert_eq!(count("B"), 1749_u32.into());
+        assert_eq!(count("C"), 298_u32.into());
+        assert_eq!(count("H"), 161_u32.into());
+        assert_eq!(count("N"), 865_u32.into());
+
+        let histogram = counts(formula.histogram_at::<BigUint>(100).unwrap());
+        assert!(histogram.iter().any(|count| count.bits() > 64));
+
+        let formula = input_generator(include_str!("../input/2021/day14.txt"));
+        assert_eq!(
+            diff(&formula.histogram_at(40).unwrap()),
+            3_906_445_077_999_u64.into()
+        );
+    }
+
+    #[test]
+    fn histogram_repeated_rules() {
+        let formula = input_generator("ABA\n\nAB -> C\nAB -> C\nBA -> A\nBA -> B");
+        let histogram = counts(formula.histogram_at::<BigUint>(1).unwrap());
+
+        assert_eq!(formula.expand(1), Ok("ACBBA".into()));
+        assert_eq!(histogram, [2_u32, 2, 1].map(BigUint::from).to_vec(),);
+    }
+
+    #[test]
+    fn histogram_modular() {
+        const M: u64 = 1_000_000_007;
+        const STEPS: u64 = 1_000_000_000_000;
+
+        let formula = input_generator(EXAMPLE);
+        let big = counts(formula.histogram_at::<BigUint>(100).unwrap());
+        let modular = counts(formula.histogram_at::<Modular<M>>(100).unwrap());
+        assert_eq!(
+            big.iter()
+                .map(|count| Modular::<M>((count % M).try_into().unwrap()))
+                .collect_vec(),
+            modular
+        );
+
+        // Every step doubles the gaps between elements, so the length after n
+        // steps is (len - 1) * 2^n + 1.
+        let total = counts(formula.histogram_at::<Modular<M>>(STEPS).unwrap())
+            .iter()
+            .fold(Modular(0), |acc, count| acc.add(count));
+        let doublings = mat_pow(Array2::from_elem((1, 1), Modular::<M>(2)), STEPS)[(0, 0)];
+        assert_eq!(total, doublings.mul(&Modular(3)).add(&Modular(1)));
+    }
+
+    #[test]
+    fn histogram() {
+        let formula = input_generator(EXAMPLE);
+
+        assert_eq!(
+            formula.histogram(10),
+            Ok(Histogram {
+                counts: vec![
+                    ("B".into(), 1749),
+                    ("N".into(), 865),
+                    ("C".into(), 298),
+                    ("H".into(), 161)
+                ],
+                len: 3073,
+            })
+        );
+    }
+
+    #[test]
+    fn histogram_overflow() {
+        let formula = input_generator(EXAMPLE);
+
+        assert!(formula.histogram(60).is_ok());
+        assert_eq!(
+            formula.histogram(70),
+            Err(HistogramError::Overflow { step: 64 })
+        );
+    }
+
+    #[test]
+    fn expand() {
+        let formula = input_generator(EXAMPLE);
+
+        assert_eq!(formula.expand(0), Ok("NNCB".into()));
+        assert_eq!(formula.expand(1), Ok("NCNBCHB".into()));
+        assert_eq!(
+            formula.expand(4),
+            Ok("NBBNBNBBCCNBCNCCNBBNBBNBBBNBBNBBCBHCBHHNHCBBCBHCB".into())
+        );
+
+        let formula = input_generator("Na He Na\n\nNa He -> B\nHe Na -> B");
+        assert_eq!(formula.expand(1), Ok("Na B He B Na".into()));
+    }
+
+    #[test]
+    fn missing_rule() {
+        let formula = input_generator("NNCB\n\nNN -> C\nNC -> B\nCN -> C\nCB -> H");
+        let missing = MissingRule {
+            pair: ("N".into(), "B".into()),
+            step: 2,
+        };
+
+        assert_eq!(formula.expand(2), Err(missing.clone()));
+        assert_eq!(formula.histogram(2), Err(missing.clone().into()));
+        assert_eq!(formula.histogram_at::<BigUint>(2), Err(missing));
+        assert!(formula.histogram_at::<BigUint>(1).is_ok());
+        assert_eq!(formula.histogram(1).map(|h| h.len), Ok(7));
+    }
+
+    #[test]
+    fn expand_matches_histogram() {
+        let mut rng = Lcg::new(0x2021);
+        let mut random = |n: usize| rng.below(n);
+
+        for _ in 0..50 {
+            let elements = &"ABCDE"[..2 + random(4)];
+            let pick = |i: usize| &elements[i..=i];
+
+            let template = (0..2 + random(5))
+                .map(|_| pick(random(elements.len())))
+                .collect::<String>();
+            let rules = elements
+                .chars()
+                .cartesian_product(elements.chars())
+                .map(|(a, b)| format!("{}{} -> {}", a, b, pick(random(elements.len()))))
+                .join("\n");
+            let formula = input_generator(&format!("{}\n\n{}", template, rules));
+
+            for steps in 0..=6 {
+                let expanded = formula.expand(steps).unwrap();
+                let histogram = formula.histogram(steps).unwrap();
+
+                assert_eq!(expanded.len() as u64, histogram.len);
+                for (symbol, count) in histogram.counts {
+                    assert_eq!(expanded.matches(symbol.as_str()).count() as u64, count);
+                }
+            }
+        }
+    }
 }
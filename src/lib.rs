@@ -17,4 +17,7 @@ pub mod day14;
 pub mod day15;
 pub mod day16;
 
+#[cfg(test)]
+mod test_util;
+
 aoc_lib! { year = 2021 }